@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use flumedb::flume_log::Error;
+use serde::{Deserialize, Serialize};
+
+// Labels and notes on messages, by key, kept next to a log in
+// `<log>.annotations.json`, so they can be shared and reviewed alongside it:
+//
+//   { "%abc...sha256": { "labels": ["spam"], "notes": ["posted 40 times"] } }
+//
+// Keys are sorted, so the file diffs cleanly.
+pub struct Annotations {
+    path: PathBuf,
+    entries: BTreeMap<String, Annotation>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Annotation {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+pub fn path_for(log_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.annotations.json", log_path))
+}
+
+impl Annotations {
+    // The annotations of the log at `log_path`; none if there's no sidecar.
+    pub fn load(log_path: &str) -> Result<Annotations, Error> {
+        let path = path_for(log_path);
+        let entries = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Annotations { path, entries })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Annotation> {
+        self.entries.get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Annotation)> {
+        self.entries.iter()
+    }
+
+    pub fn add(&mut self, key: &str, labels: &[&str], note: Option<&str>) {
+        let a = self.entries.entry(key.to_string()).or_default();
+        for l in labels {
+            if !a.labels.iter().any(|have| have == l) {
+                a.labels.push(l.to_string());
+            }
+        }
+        a.notes.extend(note.map(str::to_string));
+    }
+
+    // Written to a temporary file first, so a crash can't lose what was
+    // already there.
+    pub fn save(&self) -> Result<(), Error> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.entries)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Annotation {
+    // `[label, label] note; note`, for one line of text.
+    pub fn summary(&self) -> String {
+        let mut s = String::new();
+        if !self.labels.is_empty() {
+            s.push_str(&format!("[{}]", self.labels.join(", ")));
+        }
+        if !self.notes.is_empty() {
+            if !s.is_empty() {
+                s.push(' ');
+            }
+            s.push_str(&self.notes.join("; "));
+        }
+        s
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::msg;
+
+// Summaries for the message types of ssb apps, for `stats --apps`. Each
+// analyzer sees every message in the log (apps often attach data with
+// `about` messages pointing at their own), and reports once at the end.
+pub trait Analyzer {
+    fn name(&self) -> &'static str;
+    fn add(&mut self, v: &Value);
+    fn report(&self);
+}
+
+// Every known analyzer; add new ones here.
+pub fn registry() -> Vec<Box<dyn Analyzer>> {
+    vec![
+        Box::new(Gatherings::default()),
+        Box::new(Chess::default()),
+        Box::new(Bookclub::default()),
+    ]
+}
+
+fn str_field<'a>(v: &'a Value, name: &str) -> Option<&'a str> {
+    msg::content(v)
+        .and_then(|c| c.get(name))
+        .and_then(|f| f.as_str())
+}
+
+// `about` messages that describe another message, eg. a gathering or a book.
+fn about_message(v: &Value) -> Option<&str> {
+    if msg::content_type(v) != Some("about") {
+        return None;
+    }
+    str_field(v, "about").filter(|a| a.starts_with('%'))
+}
+
+// `gathering` messages, with titles and attendees from abouts.
+#[derive(Default)]
+struct Gatherings {
+    titles: HashMap<String, String>,
+    // gathering -> attendees; abouts can come before the gathering itself
+    attendees: HashMap<String, HashSet<String>>,
+    gatherings: HashSet<String>,
+}
+
+impl Analyzer for Gatherings {
+    fn name(&self) -> &'static str {
+        "gatherings"
+    }
+
+    fn add(&mut self, v: &Value) {
+        if msg::content_type(v) == Some("gathering") {
+            if let Some(k) = msg::key(v) {
+                self.gatherings.insert(k.to_string());
+            }
+            return;
+        }
+        let about = match about_message(v) {
+            Some(a) => a.to_string(),
+            None => return,
+        };
+        if let Some(title) = str_field(v, "title") {
+            self.titles.insert(about.clone(), title.to_string());
+        }
+        let attendee = msg::content(v).and_then(|c| c.get("attendee"));
+        if let Some(link) = attendee
+            .and_then(|a| a.get("link"))
+            .and_then(|l| l.as_str())
+        {
+            let going = self.attendees.entry(about).or_default();
+            if attendee.and_then(|a| a.get("remove")).is_some() {
+                going.remove(link);
+            } else {
+                going.insert(link.to_string());
+            }
+        }
+    }
+
+    fn report(&self) {
+        let mut counts: Vec<(usize, &String)> = self
+            .gatherings
+            .iter()
+            .map(|g| (self.attendees.get(g).map_or(0, |a| a.len()), g))
+            .collect();
+        counts.sort_by(|a, b| b.cmp(a));
+        let total: usize = counts.iter().map(|(n, _)| n).sum();
+        println!("{} gatherings, {} attendances.", counts.len(), total);
+        for (n, g) in counts.iter().take(10) {
+            let title = self.titles.get(*g).map_or("(untitled)", |t| t.as_str());
+            println!("  {:>6} attending  {}  {}", n, title, g);
+        }
+    }
+}
+
+// The chess app: games start with an invite and end with a game_end.
+#[derive(Default)]
+struct Chess {
+    invites: u64,
+    moves: u64,
+    endings: BTreeMap<String, u64>,
+    players: HashSet<String>,
+}
+
+impl Analyzer for Chess {
+    fn name(&self) -> &'static str {
+        "chess"
+    }
+
+    fn add(&mut self, v: &Value) {
+        match msg::content_type(v) {
+            Some("chess_invite") => self.invites += 1,
+            Some("chess_move") => self.moves += 1,
+            Some("chess_game_end") => {
+                let status = str_field(v, "status").unwrap_or("unknown");
+                *self.endings.entry(status.to_string()).or_default() += 1;
+            }
+            _ => return,
+        }
+        if let Some(a) = msg::author(v) {
+            self.players.insert(a.to_string());
+        }
+    }
+
+    fn report(&self) {
+        println!(
+            "{} games started, {} moves, {} games finished, {} players.",
+            self.invites,
+            self.moves,
+            self.endings.values().sum::<u64>(),
+            self.players.len()
+        );
+        for (status, n) in &self.endings {
+            println!("  {:>6}  {}", n, status);
+        }
+    }
+}
+
+// `bookclub` messages, with ratings and reviews from abouts.
+#[derive(Default)]
+struct Bookclub {
+    books: HashSet<String>,
+    reviews: HashMap<String, u64>,
+}
+
+impl Analyzer for Bookclub {
+    fn name(&self) -> &'static str {
+        "bookclub"
+    }
+
+    fn add(&mut self, v: &Value) {
+        if msg::content_type(v) == Some("bookclub") {
+            if let Some(k) = msg::key(v) {
+                self.books.insert(k.to_string());
+            }
+            return;
+        }
+        if let Some(about) = about_message(v) {
+            let c = msg::content(v).unwrap();
+            if c.get("rating").is_some() || c.get("review").is_some() {
+                *self.reviews.entry(about.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    fn report(&self) {
+        let reviews: u64 = self.books.iter().filter_map(|b| self.reviews.get(b)).sum();
+        let reviewed = self
+            .books
+            .iter()
+            .filter(|b| self.reviews.contains_key(*b))
+            .count();
+        println!(
+            "{} books, {} reviewed, {} ratings and reviews.",
+            self.books.len(),
+            reviewed,
+            reviews
+        );
+    }
+}
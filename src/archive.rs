@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flumedb::flume_log::{Error, FlumeLog};
+use flumedb::offset_log::OffsetLog;
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::frame;
+use crate::progress::Progress;
+use crate::units;
+
+// An archive stores whole copies of logs, byte for byte, sharing the frames
+// they have in common. Each frame's data is a chunk, stored once:
+//   chunks.offset      every unique chunk
+//   chunks.idx         a (sha256, offset in chunks.offset) record per chunk
+//   copies/<name>.json what was archived under <name>
+//   copies/<name>.chunks  the chunk offsets making up that copy, in order
+//   lock               held while a command uses the archive
+//
+// Rebuilding a copy by appending its chunks in order reproduces the original
+// log exactly, since frame offsets only depend on the frames before them.
+pub struct Archive {
+    dir: PathBuf,
+    chunks: OffsetLog<u32>,
+    index: HashMap<[u8; 32], u64>,
+    index_file: BufWriter<File>,
+    _lock: File,
+}
+
+const INDEX_RECORD_SIZE: usize = 40;
+
+#[derive(Serialize, Deserialize)]
+pub struct Copy {
+    pub source: String,
+    pub added: u64,
+    pub frames: u64,
+    pub size: u64,
+}
+
+impl Archive {
+    pub fn open(dir: &Path) -> Result<Archive, Error> {
+        fs::create_dir_all(dir.join("copies"))?;
+        let lock = File::create(dir.join("lock"))?;
+        if lock.try_lock_exclusive().is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("archive `{}` is in use by another command", dir.display()),
+            )
+            .into());
+        }
+
+        let chunks_path = dir.join("chunks.offset");
+        let chunks = OffsetLog::<u32>::new(&chunks_path)?;
+        let mut index_file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join("chunks.idx"))?;
+
+        let mut index = HashMap::new();
+        let mut last = None;
+        let mut records = Vec::new();
+        index_file.read_to_end(&mut records)?;
+        // A partly written record at the end is redone below.
+        let whole = records.len() - records.len() % INDEX_RECORD_SIZE;
+        index_file.set_len(whole as u64)?;
+        for r in records[..whole].chunks(INDEX_RECORD_SIZE) {
+            let mut hash = [0; 32];
+            hash.copy_from_slice(&r[..32]);
+            let mut offset = [0; 8];
+            offset.copy_from_slice(&r[32..]);
+            let offset = u64::from_be_bytes(offset);
+            index.insert(hash, offset);
+            last = Some(offset);
+        }
+
+        let mut archive = Archive {
+            dir: dir.to_path_buf(),
+            chunks,
+            index,
+            index_file: BufWriter::new(index_file),
+            _lock: lock,
+        };
+
+        // Chunks appended by an interrupted `add` without an index record.
+        let mut frames = frame::frames_from(&chunks_path, last.unwrap_or(0))?;
+        if last.is_some() {
+            frames.next().transpose()?;
+        }
+        for f in frames {
+            let f = f?;
+            archive.record(hash(&f.data), f.offset)?;
+        }
+        archive.index_file.flush()?;
+        Ok(archive)
+    }
+
+    fn record(&mut self, hash: [u8; 32], offset: u64) -> io::Result<()> {
+        self.index_file.write_all(&hash)?;
+        self.index_file.write_all(&offset.to_be_bytes())?;
+        self.index.insert(hash, offset);
+        Ok(())
+    }
+
+    fn copy_path(&self, name: &str, ext: &str) -> PathBuf {
+        self.dir.join("copies").join(format!("{}.{}", name, ext))
+    }
+
+    // Store a copy of the log at `path` under `name`.
+    pub fn add(&mut self, path: &str, name: &str) -> Result<(), Error> {
+        if name.is_empty() || name.starts_with('.') || name.contains('/') {
+            eprintln!("Invalid copy name `{}`.", name);
+            return Ok(());
+        }
+        if self.copy_path(name, "json").exists() {
+            eprintln!("The archive already has a copy named `{}`.", name);
+            return Ok(());
+        }
+
+        let size = fs::metadata(path)?.len();
+        let mut progress = Progress::new(size);
+        let mut offsets = Vec::new();
+        let mut new_bytes: u64 = 0;
+
+        for f in frame::frames(path)? {
+            let f = f?;
+            let h = hash(&f.data);
+            let offset = match self.index.get(&h) {
+                Some(o) => *o,
+                None => {
+                    let o = self.chunks.append(&f.data)?;
+                    self.record(h, o)?;
+                    new_bytes += f.data.len() as u64 + frame::FRAMING_SIZE;
+                    o
+                }
+            };
+            offsets.extend_from_slice(&offset.to_be_bytes());
+            progress.update(f.offset, || {
+                format!("Stored {} new", units::bytes(new_bytes))
+            })?;
+        }
+        self.index_file.flush()?;
+        self.index_file.get_ref().sync_data()?;
+        progress.finish(&format!("Stored {} new", units::bytes(new_bytes)))?;
+
+        let copy = Copy {
+            source: path.to_string(),
+            added: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            frames: offsets.len() as u64 / 8,
+            size,
+        };
+        fs::write(self.copy_path(name, "chunks"), &offsets)?;
+        // The copy exists once its description does, so write that last.
+        let tmp = self.copy_path(name, "json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&copy)?)?;
+        fs::rename(&tmp, self.copy_path(name, "json"))?;
+
+        println!(
+            "Archived {} as `{}`: {} of its {} were new.",
+            path,
+            name,
+            units::bytes(new_bytes),
+            units::bytes(size)
+        );
+        self.summary()
+    }
+
+    pub fn copies(&self) -> Result<Vec<(String, Copy)>, Error> {
+        let mut copies = Vec::new();
+        for entry in fs::read_dir(self.dir.join("copies"))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let name = path.file_stem().unwrap().to_string_lossy().to_string();
+                let copy: Copy = serde_json::from_slice(&fs::read(&path)?)?;
+                copies.push((name, copy));
+            }
+        }
+        copies.sort_by(|(a, x), (b, y)| x.added.cmp(&y.added).then(a.cmp(b)));
+        Ok(copies)
+    }
+
+    pub fn summary(&self) -> Result<(), Error> {
+        let copies = self.copies()?;
+        let total: u64 = copies.iter().map(|(_, c)| c.size).sum();
+        let stored = self.chunks.end()
+            + fs::metadata(self.dir.join("chunks.idx"))?.len()
+            + copies.iter().map(|(_, c)| c.frames * 8).sum::<u64>();
+        let ratio = if stored == 0 {
+            1.0
+        } else {
+            total as f64 / stored as f64
+        };
+        println!(
+            "{} copies totalling {} are stored in {} (dedup ratio {:.1}x).",
+            copies.len(),
+            units::bytes(total),
+            units::bytes(stored),
+            ratio
+        );
+        Ok(())
+    }
+
+    pub fn copy(&self, name: &str) -> Result<Option<Copy>, Error> {
+        match fs::read(self.copy_path(name, "json")) {
+            Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Rebuild the copy `copy`, stored as `name`, into `out_log`.
+    pub fn restore(
+        &self,
+        name: &str,
+        copy: &Copy,
+        mut out_log: OffsetLog<u32>,
+    ) -> Result<(), Error> {
+        let mut progress = Progress::new(copy.frames);
+        let mut offsets = BufReader::new(File::open(self.copy_path(name, "chunks"))?);
+        let mut buf = [0; 8];
+        for i in 0..copy.frames {
+            offsets.read_exact(&mut buf)?;
+            let data = self.chunks.get(u64::from_be_bytes(buf))?;
+            out_log.append(&data)?;
+            progress.update(i, || format!("Wrote {} frames", units::count(i)))?;
+        }
+        progress.finish(&format!("Wrote {} frames", units::count(copy.frames)))?;
+
+        if out_log.end() != copy.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "restored {}, but `{}` was {}",
+                    units::bytes(out_log.end()),
+                    name,
+                    units::bytes(copy.size)
+                ),
+            )
+            .into());
+        }
+        println!(
+            "Restored `{}` ({}, {}).",
+            name,
+            copy.source,
+            units::bytes(copy.size)
+        );
+        Ok(())
+    }
+}
+
+fn hash(data: &[u8]) -> [u8; 32] {
+    let mut h = [0; 32];
+    h.copy_from_slice(&Sha256::digest(data));
+    h
+}
@@ -0,0 +1,83 @@
+use flumedb::flume_log::{Error, FlumeLog};
+use flumedb::offset_log::OffsetLog;
+
+use crate::frontier::{self, Frontier};
+use crate::merge;
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+// Copy the messages of `in_log` that are newer than `since` (the manifest of
+// the previous backup), and return the manifest for this one.
+//
+// A message older than its feed's latest backed-up message is taken to be in
+// an earlier backup already, so history fetched out of order is missed until
+// the next full backup.
+pub fn backup(
+    in_log: OffsetLog<u32>,
+    mut out_log: OffsetLog<u32>,
+    since: &Frontier,
+) -> Result<Frontier, Error> {
+    let mut progress = Progress::new(in_log.end());
+    let mut manifest = since.clone();
+    let mut count: u64 = 0;
+    let mut skipped: u64 = 0;
+
+    for e in in_log.iter() {
+        progress.update(e.offset, || {
+            format!("Copied {} new messages", units::count(count))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => {
+                skipped += 1;
+                continue;
+            }
+        };
+        let (author, seq) = match (msg::author(&v), msg::sequence(&v)) {
+            (Some(a), Some(s)) => (a, s),
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        };
+        if since.get(author).is_some_and(|latest| seq <= *latest) {
+            continue;
+        }
+        out_log.append(&e.data)?;
+        frontier::update(&mut manifest, &v);
+        count += 1;
+    }
+    progress.finish(&format!("Copied {} new messages", units::count(count)))?;
+
+    if skipped > 0 {
+        eprintln!(
+            "Skipped {} entries that aren't ssb messages",
+            units::count(skipped)
+        );
+    }
+    println!(
+        "Backed up {} new messages from {} feeds.",
+        units::count(count),
+        units::count(
+            manifest
+                .iter()
+                .filter(|(f, s)| since.get(*f) != Some(*s))
+                .count() as u64
+        )
+    );
+    Ok(manifest)
+}
+
+// Rebuild a log from a full backup followed by its deltas, in order, skipping
+// messages that appear more than once.
+pub fn restore(backups: Vec<OffsetLog<u32>>, mut out_log: OffsetLog<u32>) -> Result<(), Error> {
+    let copied = merge::copy_unique(&backups, &mut out_log, "Backup")?;
+    println!(
+        "Restored {} messages from {} backups ({} duplicates skipped).",
+        units::count(copied.messages),
+        backups.len(),
+        units::count(copied.total_duplicates())
+    );
+    Ok(())
+}
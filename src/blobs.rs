@@ -0,0 +1,73 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{stdout, BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+use crate::uri;
+
+// Every string in `v`, however deeply nested.
+fn strings<'a>(v: &'a Value, out: &mut Vec<&'a str>) {
+    match v {
+        Value::String(s) => out.push(s),
+        Value::Array(a) => a.iter().for_each(|v| strings(v, out)),
+        Value::Object(o) => o.values().for_each(|v| strings(v, out)),
+        _ => {}
+    }
+}
+
+// Every blob id referenced from public message content (mentions, image
+// fields, markdown in text, anywhere), with how many messages reference it,
+// most referenced first. The list goes to stdout, one `count  id` per line.
+pub fn blobs(log: &OffsetLog<u32>) -> Result<(), Error> {
+    let blob = Regex::new(r"&[A-Za-z0-9+/]{43}=\.sha256").unwrap();
+    let mut progress = Progress::new(log.end());
+    let mut refs: HashMap<String, u64> = HashMap::new();
+    let mut messages: u64 = 0;
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} blobs", units::count(refs.len() as u64))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let mut found = Vec::new();
+        if let Some(content) = msg::content(&v) {
+            strings(content, &mut found);
+        }
+        // A blob mentioned twice in one message is one reference.
+        let ids: HashSet<&str> = found
+            .iter()
+            .flat_map(|s| blob.find_iter(s).map(|m| m.as_str()))
+            .collect();
+        if ids.is_empty() {
+            continue;
+        }
+        messages += 1;
+        for id in ids {
+            *refs.entry(id.to_string()).or_default() += 1;
+        }
+    }
+    progress.finish(&format!("Found {} blobs", units::count(refs.len() as u64)))?;
+
+    let mut refs: Vec<(String, u64)> = refs.into_iter().collect();
+    refs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let stdout = stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for (id, count) in &refs {
+        writeln!(out, "{:>8}  {}", units::count(*count), uri::show(id))?;
+    }
+    out.flush()?;
+    eprintln!(
+        "{} blobs referenced by {} messages.",
+        units::count(refs.len() as u64),
+        units::count(messages)
+    );
+    Ok(())
+}
@@ -0,0 +1,408 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::Value;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+use crate::uri;
+
+struct Link {
+    key: String,
+    previous: Option<String>,
+}
+
+// What's wrong with one feed's chain of `previous` links.
+#[derive(Default)]
+struct FeedReport {
+    // Runs of sequence numbers that aren't in the log: history that's just
+    // missing, since the messages after them still point at messages that
+    // would be there.
+    gaps: Vec<(u64, u64)>,
+    // Messages whose `previous` contradicts what the log has.
+    broken: Vec<String>,
+}
+
+pub struct Summary {
+    pub feeds: usize,
+    pub missing_history: usize,
+    pub broken: usize,
+}
+
+// Link every feed's messages by sequence and check each message's `previous`
+// against the one before it in the log. A message whose predecessor isn't in
+// the log at all is missing history, which is normal for a partially
+// replicated feed. A `previous` that doesn't match the predecessor that is
+// there, a first message with a `previous`, or two messages with the same
+// sequence number, is a broken chain. Feeds in `skip` aren't checked.
+pub fn verify(log: &OffsetLog<u32>, skip: &HashSet<String>) -> Result<Summary, Error> {
+    let mut progress = Progress::new(log.end());
+    let mut feeds: HashMap<String, BTreeMap<u64, Vec<Link>>> = HashMap::new();
+    let mut count: u64 = 0;
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Read {} messages", units::count(count))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let (key, author, seq) = match (msg::key(&v), msg::author(&v), msg::sequence(&v)) {
+            (Some(k), Some(a), Some(s)) if !skip.contains(a) => (k, a, s),
+            _ => continue,
+        };
+        let previous = v
+            .get("value")
+            .and_then(|v| v.get("previous"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let links = feeds
+            .entry(author.to_string())
+            .or_default()
+            .entry(seq)
+            .or_default();
+        // The same message twice (eg. appended again) isn't a fork.
+        if !links.iter().any(|l| l.key == key) {
+            links.push(Link {
+                key: key.to_string(),
+                previous,
+            });
+        }
+        count += 1;
+    }
+    progress.finish(&format!("Read {} messages", units::count(count)))?;
+
+    let mut reports: BTreeMap<&String, FeedReport> = BTreeMap::new();
+    for (feed, chain) in &feeds {
+        let mut r = FeedReport::default();
+        let mut expected_seq = 1;
+        for (&seq, links) in chain {
+            if seq > expected_seq {
+                r.gaps.push((expected_seq, seq - 1));
+            }
+            expected_seq = seq + 1;
+
+            if links.len() > 1 {
+                let keys: Vec<_> = links.iter().map(|l| uri::show(&l.key)).collect();
+                r.broken
+                    .push(format!("#{} is forked: {}", seq, keys.join(", ")));
+                continue;
+            }
+            let link = &links[0];
+            let before = seq
+                .checked_sub(1)
+                .filter(|p| *p > 0)
+                .and_then(|p| chain.get(&p));
+            match (&link.previous, before) {
+                (None, _) if seq == 1 => {}
+                (Some(p), _) if seq == 1 => r.broken.push(format!(
+                    "#1 {} has a previous ({})",
+                    uri::show(&link.key),
+                    uri::show(p)
+                )),
+                (None, _) => {
+                    r.broken
+                        .push(format!("#{} {} has no previous", seq, uri::show(&link.key)))
+                }
+                // Missing history: nothing to check against.
+                (Some(_), None) => {}
+                (Some(p), Some(before)) => {
+                    if !before.iter().any(|b| &b.key == p) {
+                        r.broken.push(format!(
+                            "#{} {} points to {}, but #{} is {}",
+                            seq,
+                            uri::show(&link.key),
+                            uri::show(p),
+                            seq - 1,
+                            uri::show(&before[0].key)
+                        ));
+                    }
+                }
+            }
+        }
+        if !r.gaps.is_empty() || !r.broken.is_empty() {
+            reports.insert(feed, r);
+        }
+    }
+
+    let mut summary = Summary {
+        feeds: feeds.len(),
+        missing_history: 0,
+        broken: 0,
+    };
+    for (feed, r) in &reports {
+        println!("{}", uri::show(feed));
+        for (from, to) in &r.gaps {
+            if from == to {
+                println!("  missing history: #{}", from);
+            } else {
+                println!("  missing history: #{}-#{}", from, to);
+            }
+        }
+        for b in &r.broken {
+            println!("  broken chain: {}", b);
+        }
+        if !r.broken.is_empty() {
+            summary.broken += 1;
+        } else {
+            summary.missing_history += 1;
+        }
+    }
+    println!(
+        "{} feeds: {} complete, {} missing history, {} with broken chains.",
+        units::count(summary.feeds as u64),
+        units::count((summary.feeds - summary.missing_history - summary.broken) as u64),
+        units::count(summary.missing_history as u64),
+        units::count(summary.broken as u64)
+    );
+    Ok(summary)
+}
+
+// Feeds whose earliest message in the log isn't their first (sequence 1),
+// with how many messages are missing before it, most first. Those feeds can
+// only be validated from that message on.
+pub fn partial(log: &OffsetLog<u32>, skip: &HashSet<String>) -> Result<(), Error> {
+    let mut progress = Progress::new(log.end());
+    let mut earliest: HashMap<String, u64> = HashMap::new();
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} feeds", units::count(earliest.len() as u64))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        if let (Some(author), Some(seq)) = (msg::author(&v), msg::sequence(&v)) {
+            if skip.contains(author) {
+                continue;
+            }
+            let min = earliest.entry(author.to_string()).or_insert(seq);
+            *min = (*min).min(seq);
+        }
+    }
+    progress.finish(&format!(
+        "Found {} feeds",
+        units::count(earliest.len() as u64)
+    ))?;
+
+    let mut partial: Vec<(u64, &String)> = earliest
+        .iter()
+        .filter(|(_, seq)| **seq > 1)
+        .map(|(feed, seq)| (*seq, feed))
+        .collect();
+    partial.sort_by(|a, b| b.cmp(a));
+    for (seq, feed) in &partial {
+        println!(
+            "{:>10} missing  earliest #{}  {}",
+            units::count(seq - 1),
+            seq,
+            uri::show(feed)
+        );
+    }
+    println!(
+        "{} of {} feeds have partial history ({} messages missing).",
+        units::count(partial.len() as u64),
+        units::count(earliest.len() as u64),
+        units::count(partial.iter().map(|(seq, _)| seq - 1).sum::<u64>())
+    );
+    Ok(())
+}
+
+struct Claim {
+    key: String,
+    offset: u64,
+    sequence: u64,
+}
+
+// Forked feeds: an author with two different messages at the same sequence
+// number, or two messages (at different sequence numbers) with the same
+// `previous`. Prints the author, then each fork with the messages' keys and
+// offsets. Returns the number of forks found.
+pub fn forks(log: &OffsetLog<u32>) -> Result<usize, Error> {
+    let mut progress = Progress::new(log.end());
+    let mut by_seq: HashMap<String, BTreeMap<u64, Vec<Claim>>> = HashMap::new();
+    let mut by_previous: HashMap<(String, String), Vec<Claim>> = HashMap::new();
+    let mut count: u64 = 0;
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Read {} messages", units::count(count))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let (key, author, seq) = match (msg::key(&v), msg::author(&v), msg::sequence(&v)) {
+            (Some(k), Some(a), Some(s)) => (k, a, s),
+            _ => continue,
+        };
+        count += 1;
+        let claim = || Claim {
+            key: key.to_string(),
+            offset: e.offset,
+            sequence: seq,
+        };
+        let claims = by_seq
+            .entry(author.to_string())
+            .or_default()
+            .entry(seq)
+            .or_default();
+        // The same message twice (eg. appended again) isn't a fork.
+        if claims.iter().any(|c| c.key == key) {
+            continue;
+        }
+        claims.push(claim());
+        if let Some(previous) = v
+            .get("value")
+            .and_then(|v| v.get("previous"))
+            .and_then(Value::as_str)
+        {
+            by_previous
+                .entry((author.to_string(), previous.to_string()))
+                .or_default()
+                .push(claim());
+        }
+    }
+    progress.finish(&format!("Read {} messages", units::count(count)))?;
+
+    let mut reports: BTreeMap<&String, Vec<String>> = BTreeMap::new();
+    let list = |claims: &[Claim], with_seq: bool| {
+        claims
+            .iter()
+            .map(|c| {
+                if with_seq {
+                    format!(
+                        "{} (#{}, offset {})",
+                        uri::show(&c.key),
+                        c.sequence,
+                        c.offset
+                    )
+                } else {
+                    format!("{} (offset {})", uri::show(&c.key), c.offset)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    for (author, seqs) in &by_seq {
+        for (seq, claims) in seqs.iter().filter(|(_, c)| c.len() > 1) {
+            reports
+                .entry(author)
+                .or_default()
+                .push(format!("#{}: {}", seq, list(claims, false)));
+        }
+    }
+    for ((author, previous), claims) in &by_previous {
+        // Forks at one sequence number are already reported above.
+        if claims.iter().all(|c| c.sequence == claims[0].sequence) {
+            continue;
+        }
+        reports.entry(author).or_default().push(format!(
+            "previous {}: {}",
+            uri::show(previous),
+            list(claims, true)
+        ));
+    }
+
+    let mut forks = 0;
+    for (author, lines) in &reports {
+        println!("{}", uri::show(author));
+        for l in lines {
+            println!("  {}", l);
+        }
+        forks += lines.len();
+    }
+    println!(
+        "{} forks in {} of {} feeds.",
+        units::count(forks as u64),
+        units::count(reports.len() as u64),
+        units::count(by_seq.len() as u64)
+    );
+    Ok(forks)
+}
+
+// Runs of consecutive numbers in `seqs`, which must be sorted and deduped.
+fn runs(seqs: &[u64]) -> Vec<(u64, u64)> {
+    let mut runs: Vec<(u64, u64)> = Vec::new();
+    for &s in seqs {
+        match runs.last_mut() {
+            Some(r) if r.1 + 1 == s => r.1 = s,
+            _ => runs.push((s, s)),
+        }
+    }
+    runs
+}
+
+fn format_runs(runs: &[(u64, u64)]) -> String {
+    runs.iter()
+        .map(|(from, to)| {
+            if from == to {
+                from.to_string()
+            } else {
+                format!("{}-{}", from, to)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// The sequence numbers each feed has in the log and those it's missing,
+// from 1 up to its latest, going by sequence numbers alone: nothing is
+// checked against `previous` links or hashes. Only feeds with gaps are
+// listed, unless `all`.
+pub fn gaps(log: &OffsetLog<u32>, all: bool) -> Result<(), Error> {
+    let mut progress = Progress::new(log.end());
+    let mut feeds: HashMap<String, Vec<u64>> = HashMap::new();
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} feeds", units::count(feeds.len() as u64))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        if let (Some(author), Some(seq)) = (msg::author(&v), msg::sequence(&v)) {
+            feeds.entry(author.to_string()).or_default().push(seq);
+        }
+    }
+    progress.finish(&format!("Found {} feeds", units::count(feeds.len() as u64)))?;
+
+    let mut feeds: Vec<(String, Vec<u64>)> = feeds.into_iter().collect();
+    feeds.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    let (mut with_gaps, mut missing) = (0, 0);
+    for (id, seqs) in &mut feeds {
+        seqs.sort_unstable();
+        seqs.dedup();
+        let have = runs(seqs);
+        let mut gaps = Vec::new();
+        let mut next = 1;
+        for (from, to) in &have {
+            if *from > next {
+                gaps.push((next, from - 1));
+            }
+            next = to + 1;
+        }
+        if gaps.is_empty() {
+            if all {
+                println!("{}: has {}", uri::show(id), format_runs(&have));
+            }
+            continue;
+        }
+        with_gaps += 1;
+        missing += gaps.iter().map(|(from, to)| to - from + 1).sum::<u64>();
+        println!(
+            "{}: has {}; missing {}",
+            uri::show(id),
+            format_runs(&have),
+            format_runs(&gaps)
+        );
+    }
+    println!(
+        "{} of {} feeds have gaps ({} messages missing).",
+        units::count(with_gaps),
+        units::count(feeds.len() as u64),
+        units::count(missing)
+    );
+    Ok(())
+}
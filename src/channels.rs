@@ -0,0 +1,87 @@
+use std::collections::{HashMap, HashSet};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+#[derive(Default)]
+struct Channel {
+    messages: u64,
+    // Messages with this `channel` field, and those that only tag it with a
+    // mention or hashtag.
+    by_field: u64,
+    by_tag: u64,
+    authors: HashSet<String>,
+}
+
+// Messages and distinct authors per channel, by `channel` field or hashtag,
+// the `top` busiest first. Names are compared without case.
+pub fn channels(log: &OffsetLog<u32>, top: usize) -> Result<(), Error> {
+    let mut progress = Progress::new(log.end());
+    let mut channels: HashMap<String, Channel> = HashMap::new();
+    let (mut messages, mut in_any): (u64, u64) = (0, 0);
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} channels", units::count(channels.len() as u64))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let author = match msg::author(&v) {
+            Some(a) => a,
+            None => continue,
+        };
+        messages += 1;
+        let names = msg::channels(&v);
+        if names.is_empty() {
+            continue;
+        }
+        in_any += 1;
+        let field = msg::channel(&v).map(|c| c.trim_start_matches('#').to_ascii_lowercase());
+        for name in names {
+            let by_field = field.as_ref() == Some(&name);
+            let c = channels.entry(name).or_default();
+            c.messages += 1;
+            if by_field {
+                c.by_field += 1;
+            } else {
+                c.by_tag += 1;
+            }
+            if !c.authors.contains(author) {
+                c.authors.insert(author.to_string());
+            }
+        }
+    }
+    progress.finish(&format!(
+        "Found {} channels",
+        units::count(channels.len() as u64)
+    ))?;
+
+    let mut sorted: Vec<(&String, &Channel)> = channels.iter().collect();
+    sorted.sort_by(|a, b| b.1.messages.cmp(&a.1.messages).then(a.0.cmp(b.0)));
+    println!(
+        "{:>10} {:>10} {:>10} {:>8}  channel",
+        "messages", "field", "hashtag", "authors"
+    );
+    for (name, c) in sorted.iter().take(top) {
+        println!(
+            "{:>10} {:>10} {:>10} {:>8}  #{}",
+            units::count(c.messages),
+            units::count(c.by_field),
+            units::count(c.by_tag),
+            units::count(c.authors.len() as u64),
+            name
+        );
+    }
+    println!(
+        "{} channels; {} of {} messages are in at least one.",
+        units::count(channels.len() as u64),
+        units::count(in_any),
+        units::count(messages)
+    );
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+use flumedb::flume_log::Error;
+use sha2::{Digest, Sha256};
+
+use crate::frame;
+use crate::output;
+use crate::progress::Progress;
+use crate::units;
+
+// A sidecar of SHA-256 digests, to check a cold copy of a log for bit rot
+// years later without verifying every signature:
+//
+//   feedrick-checksums sha256
+//   size 1260038
+//   file <digest of the file's first `size` bytes>
+//   0 <digest of the data of the entry at offset 0>
+//   1019 ...
+//
+// The per-entry lines are optional; they say which entries are damaged when
+// the file digest doesn't match. Digests are lowercase hex.
+const HEADER: &str = "feedrick-checksums sha256";
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn invalid(path: &str, why: &str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, why)).into()
+}
+
+// The digest of the first `size` bytes of the file at `path`, or None if
+// it's shorter than that.
+fn file_digest(path: &str, size: u64) -> Result<Option<String>, Error> {
+    let mut file = File::open(path)?.take(size);
+    let mut progress = Progress::new(size);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 20];
+    let mut read: u64 = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.input(&buf[..n]);
+        read += n as u64;
+        progress.update(read, || format!("Hashed {}", units::bytes(read)))?;
+    }
+    progress.finish(&format!("Hashed {}", units::bytes(read)))?;
+    Ok(Some(hex(&hasher.result())).filter(|_| read == size))
+}
+
+// Write the digest of the log at `in_path`, and with `per_entry` of each of
+// its entries, to `out_path`.
+pub fn write(in_path: &str, out_path: &str, per_entry: bool) -> Result<(), Error> {
+    let size = fs::metadata(in_path)?.len();
+    let mut out = BufWriter::new(output::create_file(out_path)?);
+    writeln!(out, "{}", HEADER)?;
+    writeln!(out, "size {}", size)?;
+    writeln!(out, "file {}", file_digest(in_path, size)?.unwrap())?;
+
+    let mut entries: u64 = 0;
+    if per_entry {
+        let mut progress = Progress::new(size);
+        for f in frame::frames(in_path)? {
+            let f = f?;
+            writeln!(out, "{} {}", f.offset, hex(&Sha256::digest(&f.data)))?;
+            entries += 1;
+            progress.update(f.offset, || {
+                format!("Hashed {} entries", units::count(entries))
+            })?;
+        }
+        progress.finish(&format!("Hashed {} entries", units::count(entries)))?;
+    }
+    out.flush()?;
+    out.get_ref().sync_data()?;
+
+    if per_entry {
+        println!(
+            "Wrote the digests of `{}` ({}) and its {} entries to `{}`.",
+            in_path,
+            units::bytes(size),
+            units::count(entries),
+            out_path
+        );
+    } else {
+        println!(
+            "Wrote the digest of `{}` ({}) to `{}`.",
+            in_path,
+            units::bytes(size),
+            out_path
+        );
+    }
+    Ok(())
+}
+
+struct Sums {
+    size: u64,
+    file: String,
+    entries: BTreeMap<u64, String>,
+}
+
+fn read(path: &str) -> Result<Sums, Error> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let mut next = || -> Result<String, Error> {
+        lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| invalid(path, "unexpected end of file"))
+    };
+    if next()? != HEADER {
+        return Err(invalid(path, "not a feedrick checksums file"));
+    }
+    let size = next()?
+        .strip_prefix("size ")
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid(path, "bad size line"))?;
+    let file = next()?
+        .strip_prefix("file ")
+        .map(str::to_string)
+        .ok_or_else(|| invalid(path, "bad file line"))?;
+
+    let mut entries = BTreeMap::new();
+    for line in lines {
+        let line = line?;
+        let mut parts = line.splitn(2, ' ');
+        match (parts.next().and_then(|o| o.parse().ok()), parts.next()) {
+            (Some(offset), Some(digest)) => {
+                entries.insert(offset, digest.to_string());
+            }
+            _ => return Err(invalid(path, &format!("bad entry line `{}`", line))),
+        }
+    }
+    Ok(Sums {
+        size,
+        file,
+        entries,
+    })
+}
+
+// Check the log at `in_path` against the checksums in `sums_path`. Entries
+// appended since the checksums were written aren't checked. Returns whether
+// everything matched.
+pub fn verify(in_path: &str, sums_path: &str) -> Result<bool, Error> {
+    let sums = read(sums_path)?;
+    let size = fs::metadata(in_path)?.len();
+    if size < sums.size {
+        println!(
+            "`{}` is {}, shorter than the {} it was when checksummed.",
+            in_path,
+            units::bytes(size),
+            units::bytes(sums.size)
+        );
+    } else if file_digest(in_path, sums.size)?.as_ref() == Some(&sums.file) {
+        println!(
+            "OK: the first {} of `{}` match.",
+            units::bytes(sums.size),
+            in_path
+        );
+        return Ok(true);
+    } else {
+        println!("`{}` doesn't match its checksum.", in_path);
+    }
+    if sums.entries.is_empty() {
+        println!("There are no per-entry checksums to say where; see `checksum --per-entry`.");
+        return Ok(false);
+    }
+
+    let mut progress = Progress::new(sums.size);
+    let mut expected = sums.entries.iter().peekable();
+    let (mut checked, mut damaged): (u64, u64) = (0, 0);
+    for f in frame::frames(in_path)? {
+        let f = match f {
+            Ok(f) if f.offset < sums.size => f,
+            Ok(_) => break,
+            Err(e) => {
+                println!("  framing broken: {}", e);
+                break;
+            }
+        };
+        progress.update(f.offset, || {
+            format!("Checked {} entries", units::count(checked))
+        })?;
+        // Entries expected before this one weren't found at their offsets.
+        while let Some((offset, _)) = expected.peek().filter(|(o, _)| **o < f.offset) {
+            println!("  {}: entry missing", offset);
+            damaged += 1;
+            expected.next();
+        }
+        match expected.peek() {
+            Some((offset, digest)) if **offset == f.offset => {
+                if **digest != hex(&Sha256::digest(&f.data)) {
+                    println!("  {}: entry damaged", f.offset);
+                    damaged += 1;
+                }
+                expected.next();
+            }
+            _ => {
+                println!("  {}: unexpected entry", f.offset);
+                damaged += 1;
+            }
+        }
+        checked += 1;
+    }
+    progress.finish(&format!("Checked {} entries", units::count(checked)))?;
+    for (offset, _) in expected {
+        println!("  {}: entry missing", offset);
+        damaged += 1;
+    }
+    if damaged == 0 {
+        println!("Every entry matches; the damage is in the framing between them.");
+        return Ok(false);
+    }
+    println!(
+        "{} of {} entries are damaged or missing.",
+        units::count(damaged),
+        units::count(sums.entries.len() as u64)
+    );
+    Ok(false)
+}
@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use flumedb::flume_log::Error;
+use serde::Deserialize;
+
+use crate::lint::LintRule;
+use crate::uri;
+
+// User settings, read from `--config <path>` or `<config dir>/feedrick/config.json`.
+//
+//   {
+//     "lint_rules": [
+//       { "type": "post", "field": "channel", "matches": "^[a-z0-9-]+$" },
+//       { "type": "about", "max_size": 8192 }
+//     ],
+//     "skip_feeds": ["@spam...=.ed25519", "ssb:feed/classic/..."]
+//   }
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub lint_rules: Vec<LintRule>,
+    // Feeds that extract, stats (including --timeline and --compare), authors,
+    // types and verify (including --partial) leave out unless given
+    // `--no-skip-list`: spam, or known-broken legacy feeds.
+    pub skip_feeds: Vec<String>,
+}
+
+impl Config {
+    // `skip_feeds` as feed ids; they may be given as SSB URIs.
+    pub fn skip_list(&self) -> Result<HashSet<String>, Error> {
+        self.skip_feeds
+            .iter()
+            .map(|f| {
+                uri::id(f).map(|id| id.into_owned()).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("skip_feeds: `{}` isn't a feed id", f),
+                    )
+                    .into()
+                })
+            })
+            .collect()
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("feedrick").join("config.json"))
+}
+
+// An explicitly given config file must exist; a missing default one just
+// means default settings.
+pub fn load(path: Option<&str>) -> Result<Config, Error> {
+    let path = match path {
+        Some(p) => PathBuf::from(p),
+        None => match default_path() {
+            Some(p) if p.exists() => p,
+            _ => return Ok(Config::default()),
+        },
+    };
+    let bytes = fs::read(&path)?;
+    serde_json::from_slice(&bytes).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+        .into()
+    })
+}
@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde::Serialize;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+use crate::uri;
+
+// Where an author stands on a feed, going by their latest contact message
+// about it.
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Relation {
+    Follow,
+    Block,
+    // Unfollowed or unblocked.
+    None,
+}
+
+// The follow graph, from the latest contact message each author published
+// about each feed. Blocking a feed counts as unfollowing it.
+#[derive(Default)]
+pub struct Follows {
+    // author -> contact -> (sequence of the deciding message, relation)
+    edges: HashMap<String, HashMap<String, (u64, Relation)>>,
+}
+
+impl Follows {
+    pub fn scan(log: &OffsetLog<u32>) -> Result<Follows, Error> {
+        let mut follows = Follows::default();
+        let mut progress = Progress::new(log.end());
+        let mut count: u64 = 0;
+
+        for e in log.iter() {
+            progress.update(e.offset, || {
+                format!("Read {} contact messages", units::count(count))
+            })?;
+            let v = match msg::parse(&e) {
+                Some(v) => v,
+                None => continue,
+            };
+            if msg::content_type(&v) != Some("contact") {
+                continue;
+            }
+            let content = msg::content(&v).unwrap();
+            let (author, contact) = match (
+                msg::author(&v),
+                content.get("contact").and_then(|c| c.as_str()),
+            ) {
+                (Some(a), Some(c)) => (a, c),
+                _ => continue,
+            };
+            let following = content.get("following").and_then(|f| f.as_bool());
+            let blocking = content.get("blocking").and_then(|b| b.as_bool());
+            let state = match (following, blocking) {
+                (_, Some(true)) => Relation::Block,
+                (Some(true), _) => Relation::Follow,
+                (Some(false), _) | (_, Some(false)) => Relation::None,
+                _ => continue,
+            };
+            count += 1;
+
+            // Logs aren't always in feed order; the highest sequence decides.
+            let seq = msg::sequence(&v).unwrap_or(0);
+            let edge = follows
+                .edges
+                .entry(author.to_string())
+                .or_default()
+                .entry(contact.to_string())
+                .or_insert((seq, state));
+            // Unblocking alone doesn't say anything about following.
+            let unblock =
+                following.is_none() && blocking == Some(false) && edge.1 == Relation::Follow;
+            if seq >= edge.0 && !unblock {
+                *edge = (seq, state);
+            }
+        }
+        progress.finish(&format!("Read {} contact messages", units::count(count)))?;
+        Ok(follows)
+    }
+
+    pub fn followed_by<'a>(&'a self, id: &str) -> impl Iterator<Item = &'a str> {
+        self.edges
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter(|(_, (_, relation))| *relation == Relation::Follow)
+            .map(|(contact, _)| contact.as_str())
+    }
+
+    // Every feed within `max` follow hops of `root` (which is at hop 0),
+    // with its distance.
+    pub fn hops(&self, root: &str, max: u32) -> HashMap<String, u32> {
+        let mut dist = HashMap::new();
+        let mut queue = VecDeque::new();
+        dist.insert(root.to_string(), 0);
+        queue.push_back(root.to_string());
+
+        while let Some(id) = queue.pop_front() {
+            let d = dist[&id];
+            if d == max {
+                continue;
+            }
+            for next in self.followed_by(&id) {
+                if !dist.contains_key(next) {
+                    dist.insert(next.to_string(), d + 1);
+                    queue.push_back(next.to_string());
+                }
+            }
+        }
+        dist
+    }
+
+    // Every author's relation to every feed they've published a contact
+    // message about, as JSON: `{ "@author": { "@feed": "follow" } }`, with
+    // "block" or "none" for feeds they've blocked, or unfollowed or
+    // unblocked.
+    pub fn write_json<W: Write>(&self, out: W) -> Result<(), Error> {
+        let graph: BTreeMap<String, BTreeMap<String, Relation>> = self
+            .edges
+            .iter()
+            .map(|(author, contacts)| {
+                let contacts = contacts
+                    .iter()
+                    .map(|(c, (_, r))| (uri::show(c).into_owned(), *r))
+                    .collect();
+                (uri::show(author).into_owned(), contacts)
+            })
+            .collect();
+        let mut out = BufWriter::new(out);
+        serde_json::to_writer_pretty(&mut out, &graph)?;
+        writeln!(out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    // One line per author with the feeds they follow (or block), separated
+    // by spaces: `@author @feed1 @feed2`. Authors without any are left out.
+    pub fn write_adjacency<W: Write>(&self, out: W, relation: Relation) -> Result<(), Error> {
+        let mut out = BufWriter::new(out);
+        let mut authors: Vec<&String> = self.edges.keys().collect();
+        authors.sort_unstable();
+        for author in authors {
+            let mut contacts: Vec<&String> = self.edges[author]
+                .iter()
+                .filter(|(_, (_, r))| *r == relation)
+                .map(|(c, _)| c)
+                .collect();
+            if contacts.is_empty() {
+                continue;
+            }
+            contacts.sort_unstable();
+            write!(out, "{}", uri::show(author))?;
+            for c in contacts {
+                write!(out, " {}", uri::show(c))?;
+            }
+            writeln!(out)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, HashMap};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::report::{self, Format};
+use crate::units;
+use crate::uri;
+
+struct Entry {
+    author: String,
+    sequence: u64,
+    hash: [u8; 32],
+}
+
+// Messages (key, sequence) of one feed that differ between the logs.
+#[derive(Default)]
+struct FeedDiff {
+    only_a: Vec<(String, u64)>,
+    only_b: Vec<(String, u64)>,
+    // Same key in both, but the entries' bytes differ.
+    changed: Vec<(String, u64)>,
+}
+
+fn scan<F>(log: &OffsetLog<u32>, name: &str, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(&str, Entry),
+{
+    let mut progress = Progress::new(log.end());
+    let mut count: u64 = 0;
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Read {} messages from {}", units::count(count), name)
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        if let (Some(key), Some(author)) = (msg::key(&v), msg::author(&v)) {
+            let mut hash = [0; 32];
+            hash.copy_from_slice(&Sha256::digest(&e.data));
+            f(
+                key,
+                Entry {
+                    author: author.to_string(),
+                    sequence: msg::sequence(&v).unwrap_or(0),
+                    hash,
+                },
+            );
+            count += 1;
+        }
+    }
+    progress.finish(&format!(
+        "Read {} messages from {}",
+        units::count(count),
+        name
+    ))?;
+    Ok(())
+}
+
+// Compare two logs by message key: per feed, the messages only in `a`, only
+// in `b`, and those in both whose entries aren't byte-identical.
+pub fn diff(a: &OffsetLog<u32>, b: &OffsetLog<u32>, format: &Format) -> Result<(), Error> {
+    let mut in_a: HashMap<String, Entry> = HashMap::new();
+    scan(a, "a", |key, entry| {
+        in_a.insert(key.to_string(), entry);
+    })?;
+
+    let mut feeds: BTreeMap<String, FeedDiff> = BTreeMap::new();
+    scan(b, "b", |key, entry| match in_a.remove(key) {
+        Some(old) if old.hash != entry.hash => feeds
+            .entry(entry.author)
+            .or_default()
+            .changed
+            .push((key.to_string(), entry.sequence)),
+        Some(_) => {}
+        None => feeds
+            .entry(entry.author)
+            .or_default()
+            .only_b
+            .push((key.to_string(), entry.sequence)),
+    })?;
+    for (key, entry) in in_a {
+        feeds
+            .entry(entry.author)
+            .or_default()
+            .only_a
+            .push((key, entry.sequence));
+    }
+    for d in feeds.values_mut() {
+        d.only_a.sort_by_key(|(_, s)| *s);
+        d.only_b.sort_by_key(|(_, s)| *s);
+        d.changed.sort_by_key(|(_, s)| *s);
+    }
+
+    let total = |f: fn(&FeedDiff) -> &Vec<(String, u64)>| -> usize {
+        feeds.values().map(|d| f(d).len()).sum()
+    };
+    let (only_a, only_b, changed) = (
+        total(|d| &d.only_a),
+        total(|d| &d.only_b),
+        total(|d| &d.changed),
+    );
+
+    if let Format::Text = format {
+        println!(
+            "{} messages only in a, {} only in b, {} with different bytes, in {} feeds.",
+            units::count(only_a as u64),
+            units::count(only_b as u64),
+            units::count(changed as u64),
+            units::count(feeds.len() as u64)
+        );
+        for (id, d) in &feeds {
+            println!();
+            println!("{}", uri::show(id));
+            for (mark, msgs) in &[("-", &d.only_a), ("+", &d.only_b), ("~", &d.changed)] {
+                for (key, seq) in msgs.iter() {
+                    println!("  {} #{:<8} {}", mark, seq, uri::show(key));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let list = |msgs: &[(String, u64)]| -> Vec<Value> {
+        msgs.iter()
+            .map(|(key, seq)| json!({ "key": key, "sequence": seq }))
+            .collect()
+    };
+    let feeds: serde_json::Map<String, Value> = feeds
+        .iter()
+        .map(|(id, d)| {
+            (
+                id.clone(),
+                json!({
+                    "only_a": list(&d.only_a),
+                    "only_b": list(&d.only_b),
+                    "changed": list(&d.changed),
+                }),
+            )
+        })
+        .collect();
+    report::emit(
+        &json!({
+            "only_a": only_a,
+            "only_b": only_b,
+            "changed": changed,
+            "feeds": feeds,
+        }),
+        format,
+    )
+}
@@ -0,0 +1,202 @@
+use std::fs;
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::time::Duration;
+
+use flumedb::flume_log::Error;
+use serde_json::Value;
+
+use crate::frame;
+use crate::input::{self, Problem};
+use crate::output;
+use crate::units;
+
+const SAMPLE_SIZE: usize = 1000;
+const SBOT_PORT: u16 = 8008;
+
+// Inspect an ssb directory and print what was found, anything that looks
+// wrong, and the feedrick commands that would help.
+pub fn doctor(ssb_dir: &Path) -> Result<(), Error> {
+    let mut advice: Vec<String> = Vec::new();
+
+    println!("ssb directory: {}", ssb_dir.display());
+    if !ssb_dir.is_dir() {
+        println!("  not found");
+        return Ok(());
+    }
+
+    let log_path = ssb_dir.join("flume").join("log.offset");
+    let db2_path = ssb_dir.join("db2").join("log.bipf");
+    if db2_path.exists() {
+        println!(
+            "  found an ssb-db2 log at {} (bipf format, not supported by feedrick)",
+            db2_path.display()
+        );
+    }
+    if !log_path.exists() {
+        println!("  no flumedb offset log at {}", log_path.display());
+        return Ok(());
+    }
+    let log_len = fs::metadata(&log_path)?.len();
+    let log_str = log_path.to_string_lossy();
+    println!();
+    println!(
+        "log: {} (flumelog-offset, {})",
+        log_path.display(),
+        units::bytes(log_len)
+    );
+
+    report_views(&ssb_dir.join("flume"), log_len)?;
+
+    println!();
+    let running = sbot_running(&log_path)?;
+    match &running {
+        Some(why) => {
+            println!("sbot: appears to be running ({})", why);
+            advice.push(
+                "Stop sbot before copying the log, or copies may end with a half-written entry."
+                    .to_string(),
+            );
+        }
+        None => println!("sbot: not running"),
+    }
+
+    let free = fs2::available_space(ssb_dir)?;
+    println!("free space: {}", units::bytes(free));
+    if free < log_len {
+        advice.push(format!(
+            "Free at least {} before making a full copy of the log.",
+            units::bytes(log_len - free)
+        ));
+    }
+
+    println!();
+    println!("integrity (sampled):");
+    match input::check(&log_path)? {
+        None => println!("  framing: ok"),
+        Some(problem) => {
+            println!("  framing: {}", problem);
+            match problem {
+                Problem::Empty | Problem::AllZeroed { .. } => {}
+                Problem::Misaligned { .. } | Problem::TrailingGarbage { .. } => {
+                    advice.push(format!("Back up `{}` before attempting a repair.", log_str));
+                    advice.push(format!(
+                        "feedrick repair --in {} --out repaired.offset",
+                        log_str
+                    ));
+                }
+            }
+        }
+    }
+
+    let (sampled, bad) = sample_entries(&log_path)?;
+    println!(
+        "  entries: {} from the start and end of the log checked, {} unparseable",
+        sampled, bad
+    );
+    if bad > 0 {
+        advice.push(format!("feedrick lint --in {} --show", log_str));
+    }
+
+    println!();
+    if advice.is_empty() {
+        println!("No problems found. For an overview of the log, try:");
+        println!("  feedrick stats --in {}", log_str);
+    } else {
+        println!("Recommendations:");
+        for a in advice {
+            println!("  {}", a);
+        }
+    }
+    Ok(())
+}
+
+// List flume views next to the log. flumeview-reduce views are JSON files
+// recording the view `version` and the log offset (`seq`) they've indexed up to.
+fn report_views(flume_dir: &Path, log_len: u64) -> Result<(), Error> {
+    let mut entries: Vec<_> = fs::read_dir(flume_dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    println!("views:");
+    for e in entries {
+        let name = e.file_name().to_string_lossy().into_owned();
+        if name == "log.offset" {
+            continue;
+        }
+        let path = e.path();
+        if path.is_dir() {
+            println!("  {:<24} (directory index)", name);
+            continue;
+        }
+
+        let view: Option<Value> = fs::read(&path)
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok());
+        let version = view
+            .as_ref()
+            .and_then(|v| v.get("version"))
+            .and_then(|v| v.as_u64());
+        let seq = view
+            .as_ref()
+            .and_then(|v| v.get("seq"))
+            .and_then(|v| v.as_u64());
+        match (version, seq) {
+            (Some(version), Some(seq)) => println!(
+                "  {:<24} version {}, indexed up to {}{}",
+                name,
+                version,
+                seq,
+                if seq + frame::FRAMING_SIZE < log_len {
+                    " (behind the log)"
+                } else {
+                    ""
+                }
+            ),
+            _ => println!("  {:<24} ({})", name, units::bytes(e.metadata()?.len())),
+        }
+    }
+    Ok(())
+}
+
+// Returns why we think an sbot is running, if we do.
+fn sbot_running(log_path: &Path) -> Result<Option<String>, Error> {
+    if let Some(why) = output::in_use(log_path)? {
+        return Ok(Some(format!("the log is {}", why)));
+    }
+    let addr = SocketAddr::from(([127, 0, 0, 1], SBOT_PORT));
+    if TcpStream::connect_timeout(&addr, Duration::from_millis(200)).is_ok() {
+        return Ok(Some(format!(
+            "something is listening on port {}",
+            SBOT_PORT
+        )));
+    }
+    Ok(None)
+}
+
+// Parse the first and last SAMPLE_SIZE entries. Returns (checked, unparseable);
+// deleted (zero-filled) entries aren't counted.
+fn sample_entries(log_path: &Path) -> Result<(usize, usize), Error> {
+    let first: Vec<_> = frame::frames(log_path)?
+        .take(SAMPLE_SIZE)
+        .take_while(|f| f.is_ok())
+        .filter_map(|f| f.ok())
+        .collect();
+    // In small logs the two samples overlap.
+    let first_end = first.last().map_or(0, |f| f.next_offset());
+    let last = frame::last_frames(log_path, SAMPLE_SIZE)?
+        .into_iter()
+        .filter(|f| f.offset >= first_end);
+
+    let mut checked = 0;
+    let mut bad = 0;
+    for f in first.into_iter().chain(last) {
+        if f.is_zeroed() {
+            continue;
+        }
+        checked += 1;
+        if serde_json::from_slice::<Value>(&f.data).is_err() {
+            bad += 1;
+        }
+    }
+    Ok((checked, bad))
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use flumedb::IterAtOffset;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::annotations::Annotations;
+use crate::frame;
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+use crate::uri;
+
+// Where the last `export --since-state` run stopped: the offset of the
+// first entry it didn't see, and the log's size then.
+#[derive(Default, Serialize, Deserialize)]
+pub struct State {
+    pub offset: u64,
+    pub log_size: u64,
+}
+
+// The state in `path`; the start of the log if there's no such file yet.
+pub fn read_state(path: &str) -> Result<State, Error> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)).into()
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Written to a temporary file first, so an interrupted run leaves the old
+// state in place and the next one exports the same messages again.
+pub fn write_state(path: &str, state: &State) -> Result<(), Error> {
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+// Write each message from offset `from` on as a line of JSON: the whole
+// flume envelope (`{ key, value, timestamp }`), or with `value_only` just the
+// signed message. Entries that aren't JSON are skipped. Annotated messages
+// get an `annotations` field in their envelope. With `--uri` the envelope's
+// key is an SSB URI; the signed message is never changed. Returns the offset
+// after the last entry read.
+pub fn export<W: Write>(
+    log: &OffsetLog<u32>,
+    from: u64,
+    annotations: &Annotations,
+    out: W,
+    value_only: bool,
+) -> Result<u64, Error> {
+    let mut out = BufWriter::new(out);
+    let mut progress = Progress::new(log.end());
+    let (mut written, mut skipped): (u64, u64) = (0, 0);
+    let mut end = from;
+
+    for e in log.iter_at_offset(from) {
+        end = e.offset + e.data.len() as u64 + frame::FRAMING_SIZE;
+        progress.update(e.offset, || {
+            format!("Exported {} messages", units::count(written))
+        })?;
+        let mut v = match msg::parse(&e) {
+            Some(v) => v,
+            None => {
+                skipped += 1;
+                continue;
+            }
+        };
+        // Re-serialized rather than copied, so every message is on one line.
+        match v.get("value").filter(|_| value_only) {
+            Some(value) => writeln!(out, "{}", value)?,
+            None if value_only => {
+                skipped += 1;
+                continue;
+            }
+            None => {
+                if let Some(key) = msg::key(&v).map(str::to_string) {
+                    if let Some(a) = annotations.get(&key) {
+                        v["annotations"] = serde_json::to_value(a)?;
+                    }
+                    v["key"] = Value::String(uri::show(&key).into_owned());
+                }
+                writeln!(out, "{}", v)?
+            }
+        }
+        written += 1;
+    }
+    out.flush()?;
+    progress.finish(&format!("Exported {} messages", units::count(written)))?;
+    if skipped > 0 {
+        eprintln!(
+            "Skipped {} entries that weren't messages.",
+            units::count(skipped)
+        );
+    }
+    Ok(end)
+}
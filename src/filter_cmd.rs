@@ -0,0 +1,159 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use flumedb::flume_log::{Error, FlumeLog};
+use flumedb::log_entry::LogEntry;
+use flumedb::offset_log::OffsetLog;
+use serde_json::Value;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+const BATCH_SIZE: usize = 1000;
+
+// An external filter process. Each candidate message is written to its stdin
+// as one line of JSON, and it must answer each line, in order, with one line
+// on stdout: `true` to keep the message, anything else (`false`, `null`) to
+// drop it. The child must not buffer its output (eg. `jq --unbuffered`).
+pub struct FilterCmd {
+    child: Child,
+    lines: Option<Sender<Vec<String>>>,
+    writer: Option<JoinHandle<io::Result<()>>>,
+    verdicts: BufReader<ChildStdout>,
+}
+
+impl FilterCmd {
+    pub fn spawn(cmd: &str) -> Result<FilterCmd, Error> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let mut stdin = child.stdin.take().unwrap();
+        let verdicts = BufReader::new(child.stdout.take().unwrap());
+
+        // Write from a separate thread, so a child that answers before it has
+        // read the whole batch can't deadlock us on a full pipe.
+        let (tx, rx) = mpsc::channel::<Vec<String>>();
+        let writer = thread::spawn(move || {
+            for batch in rx {
+                for line in batch {
+                    stdin.write_all(line.as_bytes())?;
+                    stdin.write_all(b"\n")?;
+                }
+                stdin.flush()?;
+            }
+            Ok(())
+        });
+
+        Ok(FilterCmd {
+            child,
+            lines: Some(tx),
+            writer: Some(writer),
+            verdicts,
+        })
+    }
+
+    // Send a batch of messages (one JSON document per line) and collect the
+    // child's verdicts for them.
+    pub fn judge(&mut self, lines: Vec<String>) -> Result<Vec<bool>, Error> {
+        let n = lines.len();
+        if let Some(tx) = &self.lines {
+            if tx.send(lines).is_err() {
+                return Err(self.writer_error());
+            }
+        }
+
+        let mut verdicts = Vec::with_capacity(n);
+        let mut line = String::new();
+        for _ in 0..n {
+            line.clear();
+            if self.verdicts.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "filter command exited before answering every message",
+                )
+                .into());
+            }
+            verdicts.push(line.trim() == "true");
+        }
+        Ok(verdicts)
+    }
+
+    fn writer_error(&mut self) -> Error {
+        match self.writer.take().map(|w| w.join()) {
+            Some(Ok(Err(e))) => e.into(),
+            _ => io::Error::new(io::ErrorKind::BrokenPipe, "filter command stopped reading").into(),
+        }
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        // Dropping the sender closes the child's stdin once everything is written.
+        self.lines.take();
+        if let Some(Ok(Err(e))) = self.writer.take().map(|w| w.join()) {
+            return Err(e.into());
+        }
+        let status = self.child.wait()?;
+        if !status.success() {
+            eprintln!("Filter command exited with {}", status);
+        }
+        Ok(())
+    }
+}
+
+// Like `copy_log_entries`, but messages accepted by `should_consider` are
+// also passed through the filter command, in batches.
+pub fn copy_filtered<F>(
+    in_log: OffsetLog<u32>,
+    mut out_log: OffsetLog<u32>,
+    should_consider: F,
+    mut cmd: FilterCmd,
+) -> Result<(), Error>
+where
+    F: Fn(&Value) -> bool,
+{
+    let mut progress = Progress::new(in_log.end());
+    let mut count: usize = 0;
+
+    // Re-serializing guarantees each message fits on a single line.
+    let mut batch: Vec<(LogEntry, String)> = Vec::with_capacity(BATCH_SIZE);
+    let mut iter = in_log.iter().peekable();
+    while let Some(e) = iter.next() {
+        let offset = e.offset;
+        if let Some(v) = msg::parse(&e).filter(|v| should_consider(v)) {
+            batch.push((e, v.to_string()));
+        }
+
+        if batch.len() == BATCH_SIZE || (iter.peek().is_none() && !batch.is_empty()) {
+            let lines = batch.iter_mut().map(|(_, l)| std::mem::take(l)).collect();
+            let verdicts = cmd.judge(lines)?;
+            for ((e, _), keep) in batch.drain(..).zip(verdicts) {
+                if keep {
+                    out_log.append(&e.data)?;
+                    count += 1;
+                }
+            }
+        }
+
+        progress.update(offset, || {
+            format!(
+                "Copied {} messages ({})",
+                units::count(count as u64),
+                units::bytes(out_log.end())
+            )
+        })?;
+    }
+    progress.finish(&format!(
+        "Copied {} messages ({})",
+        units::count(count as u64),
+        units::bytes(out_log.end())
+    ))?;
+    cmd.finish()?;
+    println!("Done!");
+    Ok(())
+}
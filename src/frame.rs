@@ -0,0 +1,281 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// An offset log frame (with u32 offsets) is laid out as:
+//   [data length: u32 BE][data][data length: u32 BE][offset of next frame: u32 BE]
+pub const HEADER_SIZE: u64 = 4;
+pub const FOOTER_SIZE: u64 = 8;
+pub const FRAMING_SIZE: u64 = HEADER_SIZE + FOOTER_SIZE;
+
+pub struct RawFrame {
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+impl RawFrame {
+    pub fn next_offset(&self) -> u64 {
+        self.offset + FRAMING_SIZE + self.data.len() as u64
+    }
+
+    // ssb-db "deletes" a message by overwriting its data with zeros.
+    pub fn is_zeroed(&self) -> bool {
+        self.data.iter().all(|b| *b == 0)
+    }
+}
+
+#[derive(Debug)]
+pub enum FrameError {
+    Io(io::Error),
+    // Fewer bytes remain than the frame at `offset` needs.
+    Truncated {
+        offset: u64,
+        remaining: u64,
+    },
+    // The frame at `offset` claims to be longer than the whole log.
+    BadLength {
+        offset: u64,
+        len: u32,
+    },
+    // The leading and trailing length fields of the frame at `offset` disagree.
+    LengthMismatch {
+        offset: u64,
+        head: u32,
+        tail: u32,
+    },
+    // The next-frame pointer of the frame at `offset` doesn't point past its own end.
+    BadPointer {
+        offset: u64,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl FrameError {
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            FrameError::Io(_) => None,
+            FrameError::Truncated { offset, .. }
+            | FrameError::BadLength { offset, .. }
+            | FrameError::LengthMismatch { offset, .. }
+            | FrameError::BadPointer { offset, .. } => Some(*offset),
+        }
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Io(e) => write!(f, "i/o error: {}", e),
+            FrameError::Truncated { offset, remaining } => write!(
+                f,
+                "truncated frame at offset {} ({} trailing bytes)",
+                offset, remaining
+            ),
+            FrameError::BadLength { offset, len } => write!(
+                f,
+                "frame at offset {} claims impossible length {}",
+                offset, len
+            ),
+            FrameError::LengthMismatch { offset, head, tail } => write!(
+                f,
+                "frame at offset {} has leading length {} but trailing length {}",
+                offset, head, tail
+            ),
+            FrameError::BadPointer {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "frame at offset {} points to next offset {} (expected {})",
+                offset, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> FrameError {
+        FrameError::Io(e)
+    }
+}
+
+// Walks the framing of an offset log without interpreting the frame contents.
+// Stops after the first error.
+pub struct Frames<R> {
+    reader: R,
+    offset: u64,
+    end: u64,
+    failed: bool,
+}
+
+pub fn frames<P: AsRef<Path>>(path: P) -> io::Result<Frames<BufReader<File>>> {
+    frames_from(path, 0)
+}
+
+// Walk from `offset`, which must be the start of a frame, to the current end of the file.
+pub fn frames_from<P: AsRef<Path>>(path: P, offset: u64) -> io::Result<Frames<BufReader<File>>> {
+    let mut file = File::open(path)?;
+    let end = file.metadata()?.len();
+    file.seek(SeekFrom::Start(offset))?;
+    let mut frames = Frames::new(BufReader::with_capacity(1 << 20, file), end);
+    frames.offset = offset;
+    Ok(frames)
+}
+
+impl<R: Read> Frames<R> {
+    pub fn new(reader: R, end: u64) -> Frames<R> {
+        Frames {
+            reader,
+            offset: 0,
+            end,
+            failed: false,
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<RawFrame, FrameError> {
+        let offset = self.offset;
+        let remaining = self.end - offset;
+        if remaining < FRAMING_SIZE {
+            return Err(FrameError::Truncated { offset, remaining });
+        }
+
+        let head = read_u32(&mut self.reader)?;
+        if u64::from(head) > self.end {
+            return Err(FrameError::BadLength { offset, len: head });
+        }
+        if u64::from(head) + FRAMING_SIZE > remaining {
+            return Err(FrameError::Truncated { offset, remaining });
+        }
+
+        let mut data = vec![0; head as usize];
+        self.reader.read_exact(&mut data)?;
+        let tail = read_u32(&mut self.reader)?;
+        if tail != head {
+            return Err(FrameError::LengthMismatch { offset, head, tail });
+        }
+
+        let frame = RawFrame { offset, data };
+        let next = u64::from(read_u32(&mut self.reader)?);
+        if next != frame.next_offset() {
+            return Err(FrameError::BadPointer {
+                offset,
+                expected: frame.next_offset(),
+                found: next,
+            });
+        }
+
+        self.offset = next;
+        Ok(frame)
+    }
+}
+
+impl<R: Read> Iterator for Frames<R> {
+    type Item = Result<RawFrame, FrameError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.offset >= self.end {
+            return None;
+        }
+        let r = self.read_frame();
+        self.failed = r.is_err();
+        Some(r)
+    }
+}
+
+// Writes frames to a new log through a large buffer, for copies that don't
+// need to look inside the entries: much faster than appending to an
+// `OffsetLog` one entry at a time.
+pub struct FrameWriter {
+    out: BufWriter<File>,
+    end: u64,
+}
+
+impl FrameWriter {
+    // `file` should be empty, as from `output::create_file`.
+    pub fn new(file: File) -> FrameWriter {
+        FrameWriter {
+            out: BufWriter::with_capacity(1 << 20, file),
+            end: 0,
+        }
+    }
+
+    // Append a frame for `data`, returning the new end of the log.
+    pub fn append(&mut self, data: &[u8]) -> io::Result<u64> {
+        let len = (data.len() as u32).to_be_bytes();
+        self.end += FRAMING_SIZE + data.len() as u64;
+        self.out.write_all(&len)?;
+        self.out.write_all(data)?;
+        self.out.write_all(&len)?;
+        self.out.write_all(&(self.end as u32).to_be_bytes())?;
+        Ok(self.end)
+    }
+
+    // Flush the buffer and sync the log to disk.
+    pub fn finish(self) -> io::Result<u64> {
+        let file = self.out.into_inner().map_err(|e| e.into_error())?;
+        file.sync_data()?;
+        Ok(self.end)
+    }
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+// Checks that the last frame of the log ends exactly at the end of the file,
+// without walking the whole log.
+pub fn tail_is_aligned(file: &mut File) -> io::Result<bool> {
+    let end = file.metadata()?.len();
+    if end == 0 {
+        return Ok(true);
+    }
+    if end < FRAMING_SIZE {
+        return Ok(false);
+    }
+
+    file.seek(SeekFrom::Start(end - FOOTER_SIZE))?;
+    let tail = u64::from(read_u32(file)?);
+    let next = u64::from(read_u32(file)?);
+    if next != end || tail + FRAMING_SIZE > end {
+        return Ok(false);
+    }
+
+    file.seek(SeekFrom::Start(end - FRAMING_SIZE - tail))?;
+    let head = u64::from(read_u32(file)?);
+    Ok(head == tail)
+}
+
+// Read up to `n` frames backwards from the end of the log, using each frame's
+// trailing length to find its start. Stops early at anything inconsistent.
+pub fn last_frames<P: AsRef<Path>>(path: P, n: usize) -> io::Result<Vec<RawFrame>> {
+    let mut file = File::open(path)?;
+    let mut end = file.metadata()?.len();
+    let mut frames = Vec::new();
+
+    while frames.len() < n && end >= FRAMING_SIZE {
+        file.seek(SeekFrom::Start(end - FOOTER_SIZE))?;
+        let tail = u64::from(read_u32(&mut file)?);
+        let next = u64::from(read_u32(&mut file)?);
+        if next != end || tail + FRAMING_SIZE > end {
+            break;
+        }
+
+        let offset = end - FRAMING_SIZE - tail;
+        file.seek(SeekFrom::Start(offset))?;
+        if u64::from(read_u32(&mut file)?) != tail {
+            break;
+        }
+        let mut data = vec![0; tail as usize];
+        file.read_exact(&mut data)?;
+        frames.push(RawFrame { offset, data });
+        end = offset;
+    }
+    Ok(frames)
+}
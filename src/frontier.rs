@@ -0,0 +1,123 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::Value;
+
+use crate::msg;
+use crate::output;
+use crate::progress::Progress;
+use crate::units;
+use crate::uri;
+
+// The latest sequence number seen for each feed, as in an EBT vector clock:
+// `{ "@feed": 123, ... }`.
+pub type Frontier = BTreeMap<String, u64>;
+
+pub fn update(frontier: &mut Frontier, v: &Value) {
+    if let (Some(author), Some(seq)) = (msg::author(v), msg::sequence(v)) {
+        let latest = frontier.entry(author.to_string()).or_insert(0);
+        *latest = (*latest).max(seq);
+    }
+}
+
+// Also reads an sbot's EBT clock, whose feed ids may be SSB URIs and which
+// has -1 for feeds it doesn't replicate; those are left out.
+pub fn read(path: &str) -> Result<Frontier, Error> {
+    let invalid = |why: String| -> Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, why)).into()
+    };
+    let bytes = fs::read(path)?;
+    let clock: BTreeMap<String, i64> =
+        serde_json::from_slice(&bytes).map_err(|e| invalid(e.to_string()))?;
+    let mut frontier = Frontier::new();
+    for (id, seq) in clock {
+        if seq < 0 {
+            continue;
+        }
+        let id = uri::id(&id).ok_or_else(|| invalid(format!("`{}` isn't a feed id", id)))?;
+        frontier.insert(id.into_owned(), seq as u64);
+    }
+    Ok(frontier)
+}
+
+pub fn write(path: &str, frontier: &Frontier) -> Result<(), Error> {
+    output::create_file(path)?.write_all(&serde_json::to_vec_pretty(frontier)?)?;
+    Ok(())
+}
+
+// The frontier of a whole log.
+pub fn scan(log: &OffsetLog<u32>) -> Result<Frontier, Error> {
+    let mut progress = Progress::new(log.end());
+    let mut frontier = Frontier::new();
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} feeds", units::count(frontier.len() as u64))
+        })?;
+        if let Some(v) = msg::parse(&e) {
+            update(&mut frontier, &v);
+        }
+    }
+    progress.finish(&format!(
+        "Found {} feeds",
+        units::count(frontier.len() as u64)
+    ))?;
+    Ok(frontier)
+}
+
+// Feeds where frontier `a` is ahead of `b`, and where `b` is ahead of `a`,
+// with by how many messages, most first. A feed missing from one frontier
+// counts as having no messages there.
+pub fn compare(a: &Frontier, b: &Frontier) {
+    let mut ahead_a = Vec::new();
+    let mut ahead_b = Vec::new();
+    let mut equal: u64 = 0;
+    let ids: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    for id in &ids {
+        let (sa, sb) = (a.get(*id), b.get(*id));
+        let (na, nb) = (sa.copied().unwrap_or(0), sb.copied().unwrap_or(0));
+        if na > nb {
+            ahead_a.push((na - nb, *id, sa, sb));
+        } else if nb > na {
+            ahead_b.push((nb - na, *id, sa, sb));
+        } else {
+            equal += 1;
+        }
+    }
+
+    let show = |seq: Option<&u64>| seq.map_or("-".to_string(), |s| s.to_string());
+    for (name, rows) in &mut [("a", &mut ahead_a), ("b", &mut ahead_b)] {
+        if rows.is_empty() {
+            continue;
+        }
+        rows.sort_by(|x, y| y.0.cmp(&x.0).then(x.1.cmp(y.1)));
+        println!(
+            "Ahead in {} ({} feeds):",
+            name,
+            units::count(rows.len() as u64)
+        );
+        for (n, id, sa, sb) in rows.iter() {
+            println!(
+                "  {:>10}  {}  (a {}, b {})",
+                units::count(*n),
+                uri::show(id),
+                show(*sa),
+                show(*sb)
+            );
+        }
+        println!();
+    }
+    let total =
+        |rows: &[(u64, &String, Option<&u64>, Option<&u64>)]| rows.iter().map(|r| r.0).sum::<u64>();
+    println!(
+        "{} feeds: {} ahead in a ({} messages), {} ahead in b ({} messages), {} the same.",
+        units::count(ids.len() as u64),
+        units::count(ahead_a.len() as u64),
+        units::count(total(&ahead_a)),
+        units::count(ahead_b.len() as u64),
+        units::count(total(&ahead_b)),
+        units::count(equal)
+    );
+}
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashSet};
+
+use flumedb::flume_log::{Error, FlumeLog};
+use flumedb::offset_log::OffsetLog;
+use serde_json::Value;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+// git-ssb keeps a repository in messages:
+//   git-repo      announces a repo; its key is the repo id
+//   git-update    pushes refs and packs, with `repo`
+//   issue         opens an issue, with `project` (the repo id)
+//   pull-request  with `repo` (the target) and `head_repo`
+// and comments on issues and pull requests are posts with `root` set to them.
+#[derive(Default)]
+struct Repo {
+    name: Option<String>,
+    author: Option<String>,
+    updates: u64,
+    issues: u64,
+    pull_requests: u64,
+}
+
+// The repo a git-ssb message belongs to, if it's one of the types above.
+fn repo_of(v: &Value) -> Option<&str> {
+    let field = |name| {
+        msg::content(v)
+            .and_then(|c| c.get(name))
+            .and_then(|f: &Value| f.as_str())
+    };
+    match msg::content_type(v)? {
+        "git-repo" => msg::key(v),
+        "git-update" | "pull-request" => field("repo"),
+        "issue" => field("project"),
+        _ => None,
+    }
+}
+
+// Print each repo announced or updated in the log, most active first.
+pub fn summary(log: &OffsetLog<u32>) -> Result<(), Error> {
+    let mut progress = Progress::new(log.end());
+    let mut repos: BTreeMap<String, Repo> = BTreeMap::new();
+
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} repos", units::count(repos.len() as u64))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let id = match repo_of(&v) {
+            Some(id) => id.to_string(),
+            None => continue,
+        };
+        let repo = repos.entry(id).or_default();
+        match msg::content_type(&v) {
+            Some("git-repo") => {
+                repo.author = msg::author(&v).map(str::to_string);
+                repo.name = msg::content(&v)
+                    .and_then(|c| c.get("name"))
+                    .and_then(|n| n.as_str())
+                    .map(str::to_string);
+            }
+            Some("git-update") => repo.updates += 1,
+            Some("issue") => repo.issues += 1,
+            Some("pull-request") => repo.pull_requests += 1,
+            _ => {}
+        }
+    }
+    progress.finish(&format!("Found {} repos", units::count(repos.len() as u64)))?;
+
+    let announced = repos.values().filter(|r| r.author.is_some()).count();
+    println!(
+        "{} repos ({} announced in this log).",
+        repos.len(),
+        announced
+    );
+    let mut repos: Vec<_> = repos.into_iter().collect();
+    repos.sort_by_key(|(id, r)| {
+        (
+            std::cmp::Reverse(r.updates + r.issues + r.pull_requests),
+            id.clone(),
+        )
+    });
+    println!(
+        "{:>8} {:>8} {:>8}  {:<24} repo",
+        "updates", "issues", "PRs", "name"
+    );
+    for (id, r) in repos {
+        println!(
+            "{:>8} {:>8} {:>8}  {:<24} {}",
+            r.updates,
+            r.issues,
+            r.pull_requests,
+            r.name.as_deref().unwrap_or("?"),
+            id
+        );
+    }
+    Ok(())
+}
+
+// Copy every message belonging to repo `id` to `out_log`: the announcement,
+// its updates, issues and pull requests, and the comments on those. The log
+// is read twice, since comments can come before what they reply to.
+pub fn extract(in_log: OffsetLog<u32>, mut out_log: OffsetLog<u32>, id: &str) -> Result<(), Error> {
+    let mut progress = Progress::new(2 * in_log.end());
+    let mut threads: HashSet<String> = HashSet::new();
+    threads.insert(id.to_string());
+    for e in in_log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} issues and pull requests", threads.len() - 1)
+        })?;
+        if let Some(v) = msg::parse(&e) {
+            if repo_of(&v) == Some(id) {
+                if let Some(k) = msg::key(&v) {
+                    threads.insert(k.to_string());
+                }
+            }
+        }
+    }
+
+    let mut count: u64 = 0;
+    for e in in_log.iter() {
+        progress.update(in_log.end() + e.offset, || {
+            format!("Copied {} messages", units::count(count))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let root = msg::content(&v)
+            .and_then(|c| c.get("root"))
+            .and_then(|r| r.as_str());
+        let belongs = repo_of(&v) == Some(id)
+            || msg::key(&v).is_some_and(|k| threads.contains(k))
+            || root.is_some_and(|r| threads.contains(r));
+        if belongs {
+            out_log.append(&e.data)?;
+            count += 1;
+        }
+    }
+    progress.finish(&format!("Copied {} messages", units::count(count)))?;
+    println!("Copied {} messages of repo {}.", units::count(count), id);
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use std::io::{stdout, BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use regex::bytes;
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::annotations::Annotations;
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+// What to search: each entry's raw JSON, or one field of it.
+pub enum Target {
+    Raw(bytes::Regex),
+    // A dotted path into the entry, eg. `value.content.text`. Non-string
+    // values are matched as JSON.
+    Field(Vec<String>, Regex),
+}
+
+impl Target {
+    pub fn new(pattern: &str, field: Option<&str>) -> Result<Target, Error> {
+        Ok(match field {
+            Some(path) => Target::Field(
+                path.split('.').map(str::to_string).collect(),
+                Regex::new(pattern)?,
+            ),
+            None => Target::Raw(bytes::Regex::new(pattern)?),
+        })
+    }
+}
+
+// A one-line preview of each match instead of its JSON: the offset, then
+// each field (a dotted path into the entry) with its whitespace collapsed and
+// cut to `len` characters.
+pub struct Preview {
+    pub fields: Vec<Vec<String>>,
+    pub len: usize,
+}
+
+impl Preview {
+    // By default the content type and the start of the text.
+    pub fn new(fields: &[&str], len: usize) -> Preview {
+        let fields = if fields.is_empty() {
+            &["value.content.type", "value.content.text"][..]
+        } else {
+            fields
+        };
+        Preview {
+            fields: fields
+                .iter()
+                .map(|f| f.split('.').map(str::to_string).collect())
+                .collect(),
+            len,
+        }
+    }
+
+    fn line(&self, offset: u64, v: &Value) -> String {
+        let mut line = format!("{:>10}", offset);
+        for path in &self.fields {
+            let field = match path.iter().try_fold(v, |v, part| v.get(part)) {
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+                None => "-".to_string(),
+            };
+            let words: Vec<&str> = field.split_whitespace().collect();
+            let field = words.join(" ");
+            line.push_str("  ");
+            if field.chars().count() > self.len {
+                line.extend(field.chars().take(self.len.saturating_sub(1)));
+                line.push('…');
+            } else {
+                line.push_str(&field);
+            }
+        }
+        line
+    }
+}
+
+// Print every matching entry as a line of JSON: `{ "offset", "entry" }`,
+// and `"annotations"` for annotated messages; or with `preview`, as a
+// one-line preview.
+pub fn grep(
+    log: &OffsetLog<u32>,
+    annotations: &Annotations,
+    target: &Target,
+    preview: Option<&Preview>,
+) -> Result<(), Error> {
+    let stdout = stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut progress = Progress::new(log.end());
+    let mut matches: u64 = 0;
+
+    for e in log.iter() {
+        progress.update(e.offset, || format!("{} matches", units::count(matches)))?;
+        let v = match target {
+            Target::Raw(re) => {
+                if !re.is_match(&e.data) {
+                    continue;
+                }
+                match serde_json::from_slice::<Value>(&e.data) {
+                    Ok(v) => v,
+                    Err(_) => continue,
+                }
+            }
+            Target::Field(path, re) => {
+                let v: Value = match serde_json::from_slice(&e.data) {
+                    Ok(v) => v,
+                    Err(_) => continue,
+                };
+                let is_match = match path.iter().try_fold(&v, |v, part| v.get(part)) {
+                    Some(Value::String(s)) => re.is_match(s),
+                    Some(other) => re.is_match(&other.to_string()),
+                    None => false,
+                };
+                if !is_match {
+                    continue;
+                }
+                v
+            }
+        };
+        matches += 1;
+        if let Some(p) = preview {
+            writeln!(out, "{}", p.line(e.offset, &v))?;
+            continue;
+        }
+        let annotation = msg::key(&v).and_then(|k| annotations.get(k));
+        let mut line = json!({ "offset": e.offset, "entry": v });
+        if let Some(a) = annotation {
+            line["annotations"] = serde_json::to_value(a)?;
+        }
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    progress.finish(&format!("{} matches", units::count(matches)))?;
+    Ok(())
+}
@@ -0,0 +1,289 @@
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde::de::IgnoredAny;
+use serde_json::json;
+
+use crate::frame::{self, FrameError};
+use crate::progress::Progress;
+use crate::units;
+
+// Reasons an input log can't be processed as-is.
+pub enum Problem {
+    Empty,
+    AllZeroed {
+        entries: u64,
+    },
+    // The last frame is cut short, so the file length isn't frame-aligned.
+    Misaligned {
+        offset: u64,
+        trailing: u64,
+    },
+    // Everything after `offset` doesn't parse as offset log frames.
+    TrailingGarbage {
+        offset: u64,
+        trailing: u64,
+        cause: FrameError,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Empty => write!(f, "Input offset log file is empty."),
+            Problem::AllZeroed { entries } => write!(
+                f,
+                "Input offset log contains only deleted (zero-filled) entries ({} entries).",
+                units::count(*entries)
+            ),
+            Problem::Misaligned { offset, trailing } => write!(
+                f,
+                "Input offset log length isn't frame-aligned: the last {} (from offset {}) are a truncated entry.",
+                units::bytes(*trailing),
+                offset
+            ),
+            Problem::TrailingGarbage {
+                offset,
+                trailing,
+                cause,
+            } => write!(
+                f,
+                "Input offset log has {} of trailing garbage from offset {} ({}).",
+                units::bytes(*trailing),
+                offset,
+                cause
+            ),
+        }
+    }
+}
+
+// Check the framing of the log at `path`, returning the first problem found.
+// Only the last frame and the leading run of deleted entries are read, unless
+// the tail turns out to be broken, in which case the whole log is walked to
+// locate the first bad frame.
+pub fn check<P: AsRef<Path>>(path: P) -> Result<Option<Problem>, Error> {
+    let path = path.as_ref();
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(Some(Problem::Empty));
+    }
+
+    if !frame::tail_is_aligned(&mut file)? {
+        return find_broken_frame(path, len);
+    }
+
+    let mut entries = 0;
+    for f in frame::frames(path)? {
+        let f = f?;
+        if !f.is_zeroed() {
+            return Ok(None);
+        }
+        entries += 1;
+    }
+    Ok(Some(Problem::AllZeroed { entries }))
+}
+
+fn find_broken_frame(path: &Path, len: u64) -> Result<Option<Problem>, Error> {
+    for f in frame::frames(path)? {
+        if let Err(e) = f {
+            return broken_frame(e, len).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+fn broken_frame(e: FrameError, len: u64) -> Result<Problem, Error> {
+    match e {
+        FrameError::Io(e) => Err(e.into()),
+        FrameError::Truncated { offset, remaining } => Ok(Problem::Misaligned {
+            offset,
+            trailing: remaining,
+        }),
+        cause => {
+            let offset = cause.offset().unwrap_or(0);
+            Ok(Problem::TrailingGarbage {
+                offset,
+                trailing: len - offset,
+                cause,
+            })
+        }
+    }
+}
+
+// Like `check`, but walks every frame, so damage in the middle of the log
+// (eg. a torn write) is reported at its exact offset instead of surfacing
+// later as unparseable messages.
+pub fn check_strict<P: AsRef<Path>>(path: P) -> Result<Option<Problem>, Error> {
+    let path = path.as_ref();
+    let len = path.metadata()?.len();
+    if len == 0 {
+        return Ok(Some(Problem::Empty));
+    }
+
+    let mut progress = Progress::new(len);
+    let mut entries = 0;
+    let mut live = false;
+    for f in frame::frames(path)? {
+        match f {
+            Ok(f) => {
+                live = live || !f.is_zeroed();
+                entries += 1;
+                progress.update(f.offset, || {
+                    format!("Verified {} frames", units::count(entries))
+                })?;
+            }
+            Err(e) => {
+                progress.stop(
+                    e.offset().unwrap_or(0),
+                    &format!("Verified {} frames", units::count(entries)),
+                )?;
+                return broken_frame(e, len).map(Some);
+            }
+        }
+    }
+    progress.finish(&format!("Verified {} frames", units::count(entries)))?;
+
+    if live {
+        Ok(None)
+    } else {
+        Ok(Some(Problem::AllZeroed { entries }))
+    }
+}
+
+// Walk every frame of the log at `path`, checking the framing only (length
+// fields, next-frame pointers, a cut-off tail), and report the first place it
+// breaks. Returns whether the framing is sound.
+pub fn fsck<P: AsRef<Path>>(path: P) -> Result<bool, Error> {
+    let path = path.as_ref();
+    let len = path.metadata()?.len();
+    let mut progress = Progress::new(len);
+    let (mut entries, mut deleted, mut data) = (0u64, 0u64, 0u64);
+    let status = |entries| format!("Checked {} frames", units::count(entries));
+
+    for f in frame::frames(path)? {
+        match f {
+            Ok(f) => {
+                entries += 1;
+                data += f.data.len() as u64;
+                if f.is_zeroed() {
+                    deleted += 1;
+                }
+                progress.update(f.offset, || status(entries))?;
+            }
+            Err(e) => {
+                let offset = e.offset().unwrap_or(0);
+                progress.stop(offset, &status(entries))?;
+                println!(
+                    "Framing is broken at offset {}, after {} good frames:",
+                    offset, entries
+                );
+                println!("  {}", broken_frame(e, len)?);
+                return Ok(false);
+            }
+        }
+    }
+    progress.finish(&status(entries))?;
+    println!(
+        "Framing OK: {} frames ({} deleted), {} of data in {}.",
+        units::count(entries),
+        units::count(deleted),
+        units::bytes(data),
+        units::bytes(len)
+    );
+    Ok(true)
+}
+
+// Count the entries of the log at `path` from its framing alone: deleted
+// (zero-filled) ones, and those that aren't JSON, which is checked without
+// building the values. Counts up to a broken frame, reported on stderr.
+// Returns whether the whole log was counted.
+pub fn count<P: AsRef<Path>>(path: P, json: bool) -> Result<bool, Error> {
+    let path = path.as_ref();
+    let len = path.metadata()?.len();
+    let mut progress = Progress::new(len);
+    let (mut entries, mut deleted, mut unparseable) = (0u64, 0u64, 0u64);
+    let mut broken = None;
+
+    for f in frame::frames(path)? {
+        match f {
+            Ok(f) => {
+                entries += 1;
+                if f.is_zeroed() {
+                    deleted += 1;
+                } else if serde_json::from_slice::<IgnoredAny>(&f.data).is_err() {
+                    unparseable += 1;
+                }
+                progress.update(f.offset, || {
+                    format!("Counted {} entries", units::count(entries))
+                })?;
+            }
+            Err(e) => {
+                progress.stop(
+                    e.offset().unwrap_or(0),
+                    &format!("Counted {} entries", units::count(entries)),
+                )?;
+                broken = Some(broken_frame(e, len)?);
+                break;
+            }
+        }
+    }
+    if broken.is_none() {
+        progress.finish(&format!("Counted {} entries", units::count(entries)))?;
+    }
+
+    if json {
+        println!(
+            "{}",
+            json!({
+                "entries": entries,
+                "deleted": deleted,
+                "unparseable": unparseable,
+                "complete": broken.is_none(),
+            })
+        );
+    } else {
+        println!(
+            "{} entries: {} readable, {} deleted (zero-filled), {} unparseable.",
+            units::count(entries),
+            units::count(entries - deleted - unparseable),
+            units::count(deleted),
+            units::count(unparseable)
+        );
+    }
+    match broken {
+        Some(problem) => {
+            eprintln!("Counted up to a broken frame: {}", problem);
+            Ok(false)
+        }
+        None => Ok(true),
+    }
+}
+
+// Set from the global `--strict` flag.
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+// Open an input log for reading, after checking it with `check` (or
+// `check_strict`, with `--strict`). Exits the process with a diagnostic if the
+// log is unusable.
+pub fn open_log(path: &str) -> Result<OffsetLog<u32>, Error> {
+    let problem = if STRICT.load(Ordering::Relaxed) {
+        check_strict(path)?
+    } else {
+        check(path)?
+    };
+    if let Some(problem) = problem {
+        eprintln!("{}", problem);
+        process::exit(1);
+    }
+    OffsetLog::<u32>::open_read_only(path)
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::{json, Value};
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::report::{self, Format};
+use crate::units;
+
+#[derive(Default, Clone, Copy)]
+struct Counts {
+    replies: u64,
+    mentions: u64,
+    votes: u64,
+}
+
+impl Counts {
+    fn weight(&self) -> u64 {
+        self.replies + self.mentions + self.votes
+    }
+}
+
+enum Kind {
+    Reply,
+    Mention,
+    Vote,
+}
+
+#[derive(Default)]
+struct Authors {
+    ids: Vec<String>,
+    index: HashMap<String, u32>,
+}
+
+impl Authors {
+    fn intern(&mut self, id: &str) -> u32 {
+        if let Some(i) = self.index.get(id) {
+            return *i;
+        }
+        let i = self.ids.len() as u32;
+        self.ids.push(id.to_string());
+        self.index.insert(id.to_string(), i);
+        i
+    }
+}
+
+// Count replies, mentions and votes between pairs of authors, and print the
+// busiest pairs (or the whole weighted graph, as JSON or through a template).
+pub fn interactions(log: OffsetLog<u32>, top: usize, format: &Format) -> Result<(), Error> {
+    let mut progress = Progress::new(log.end());
+    let mut authors = Authors::default();
+    // message key -> author
+    let mut key_authors: HashMap<String, u32> = HashMap::new();
+    // Replies and votes point at messages, which may not have been seen yet;
+    // resolve them once the whole log has been read.
+    let mut pending: Vec<(u32, String, Kind)> = Vec::new();
+    let mut pairs: HashMap<(u32, u32), Counts> = HashMap::new();
+
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Read {} authors", units::count(authors.ids.len() as u64))
+        })?;
+
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let author = match msg::author(&v) {
+            Some(a) => authors.intern(a),
+            None => continue,
+        };
+        if let Some(key) = msg::key(&v) {
+            key_authors.insert(key.to_string(), author);
+        }
+        let content = match msg::content(&v) {
+            Some(c) => c,
+            None => continue,
+        };
+
+        let replied_to = content
+            .get("branch")
+            .and_then(|b| b.as_str())
+            .or_else(|| content.get("root").and_then(|r| r.as_str()));
+        if let Some(target) = replied_to {
+            pending.push((author, target.to_string(), Kind::Reply));
+        }
+
+        if let Some(target) = content
+            .get("vote")
+            .and_then(|v| v.get("link"))
+            .and_then(|l| l.as_str())
+            .filter(|l| l.starts_with('%'))
+        {
+            pending.push((author, target.to_string(), Kind::Vote));
+        }
+
+        for link in msg::mentioned_feeds(content) {
+            let target = authors.intern(link);
+            add(&mut pairs, author, target, Kind::Mention);
+        }
+    }
+    progress.finish(&format!(
+        "Read {} authors",
+        units::count(authors.ids.len() as u64)
+    ))?;
+
+    for (source, key, kind) in pending {
+        if let Some(target) = key_authors.get(&key) {
+            add(&mut pairs, source, *target, kind);
+        }
+    }
+
+    let mut pairs: Vec<_> = pairs.into_iter().collect();
+    pairs.sort_by(|(a, x), (b, y)| y.weight().cmp(&x.weight()).then(a.cmp(b)));
+
+    if !matches!(format, Format::Text) {
+        let edges: Vec<Value> = pairs
+            .iter()
+            .map(|((s, t), c)| {
+                json!({
+                    "source": authors.ids[*s as usize],
+                    "target": authors.ids[*t as usize],
+                    "replies": c.replies,
+                    "mentions": c.mentions,
+                    "votes": c.votes,
+                    "weight": c.weight(),
+                })
+            })
+            .collect();
+        return report::emit(&json!({ "nodes": authors.ids, "edges": edges }), format);
+    }
+
+    println!("{} interacting pairs", pairs.len());
+    for ((s, t), c) in pairs.iter().take(top) {
+        println!(
+            "{:>8}  {} -> {}  ({} replies, {} mentions, {} votes)",
+            c.weight(),
+            authors.ids[*s as usize],
+            authors.ids[*t as usize],
+            c.replies,
+            c.mentions,
+            c.votes
+        );
+    }
+    Ok(())
+}
+
+fn add(pairs: &mut HashMap<(u32, u32), Counts>, source: u32, target: u32, kind: Kind) {
+    if source == target {
+        return;
+    }
+    let c = pairs.entry((source, target)).or_default();
+    match kind {
+        Kind::Reply => c.replies += 1,
+        Kind::Mention => c.mentions += 1,
+        Kind::Vote => c.votes += 1,
+    }
+}
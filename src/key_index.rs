@@ -0,0 +1,33 @@
+use std::io::{BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+// Write a `key,author,sequence` CSV row for every message in the log, so
+// other tools can map keys to feeds without parsing the log themselves.
+// None of the three fields can contain a comma or a quote.
+pub fn write_key_index<W: Write>(log: OffsetLog<u32>, out: W) -> Result<(), Error> {
+    let mut out = BufWriter::new(out);
+    let mut progress = Progress::new(log.end());
+    let mut count: u64 = 0;
+
+    writeln!(out, "key,author,sequence")?;
+    for e in log.iter() {
+        if let Some(v) = msg::parse(&e) {
+            if let (Some(key), Some(author), Some(seq)) =
+                (msg::key(&v), msg::author(&v), msg::sequence(&v))
+            {
+                writeln!(out, "{},{},{}", key, author, seq)?;
+                count += 1;
+            }
+        }
+        progress.update(e.offset, || format!("Wrote {} keys", units::count(count)))?;
+    }
+    out.flush()?;
+    progress.finish(&format!("Wrote {} keys", units::count(count)))?;
+    Ok(())
+}
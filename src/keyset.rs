@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, stdout, BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+use crate::uri;
+
+// Message keys from a file with one key per line, in file order, without
+// repeats. Keys may be SSB URIs. Blank lines are skipped.
+pub fn read(path: &str) -> Result<Vec<String>, Error> {
+    let mut seen = HashSet::new();
+    let mut keys = Vec::new();
+    for line in fs::read_to_string(path)?.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        let key = uri::id(line).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: `{}` isn't the SSB URI of a message", path, line),
+            )
+        })?;
+        if seen.insert(key.to_string()) {
+            keys.push(key.into_owned());
+        }
+    }
+    Ok(keys)
+}
+
+fn log_keys(log: &OffsetLog<u32>) -> Result<HashSet<String>, Error> {
+    let mut progress = Progress::new(log.end());
+    let mut keys = HashSet::new();
+    for e in log.iter() {
+        if let Some(key) = msg::parse(&e).as_ref().and_then(msg::key) {
+            keys.insert(key.to_string());
+        }
+        progress.update(e.offset, || {
+            format!("Read {} keys", units::count(keys.len() as u64))
+        })?;
+    }
+    progress.finish(&format!("Read {} keys", units::count(keys.len() as u64)))?;
+    Ok(keys)
+}
+
+// Print the keys of `keys` that are in the log (or with `missing`, those
+// that aren't), one per line, in the order given.
+pub fn filter(log: &OffsetLog<u32>, keys: &[String], missing: bool) -> Result<(), Error> {
+    let in_log = log_keys(log)?;
+    let stdout = stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut count: u64 = 0;
+    for k in keys {
+        if in_log.contains(k) != missing {
+            writeln!(out, "{}", uri::show(k))?;
+            count += 1;
+        }
+    }
+    out.flush()?;
+    eprintln!(
+        "{} of {} keys {}.",
+        units::count(count),
+        units::count(keys.len() as u64),
+        if missing {
+            "missing from the log"
+        } else {
+            "present in the log"
+        }
+    );
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+use crate::uri;
+
+// A user-defined check on message content, from the `lint_rules` config list.
+// `field` is a dotted path into the content object; rules without a `type`
+// apply to every public message.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintRule {
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub msg_type: Option<String>,
+    pub field: Option<String>,
+    // The field must (or must not) be present.
+    pub exists: Option<bool>,
+    // The field, if it's a string, must match this regex.
+    pub matches: Option<String>,
+    // Serialized size limit in bytes, of the field or of the whole content.
+    pub max_size: Option<usize>,
+}
+
+struct CompiledRule<'a> {
+    rule: &'a LintRule,
+    regex: Option<Regex>,
+}
+
+impl<'a> CompiledRule<'a> {
+    fn new(rule: &'a LintRule) -> Result<CompiledRule<'a>, Error> {
+        if rule.field.is_none() && (rule.exists.is_some() || rule.matches.is_some()) {
+            return Err(invalid_rule(rule, "`exists` and `matches` need a `field`"));
+        }
+        if rule.exists.is_none() && rule.matches.is_none() && rule.max_size.is_none() {
+            return Err(invalid_rule(rule, "nothing to check"));
+        }
+        let regex = match &rule.matches {
+            Some(m) => Some(Regex::new(m)?),
+            None => None,
+        };
+        Ok(CompiledRule { rule, regex })
+    }
+
+    fn check(&self, typ: Option<&str>, content: &Value, problems: &mut Vec<String>) {
+        let rule = self.rule;
+        if rule.msg_type.is_some() && rule.msg_type.as_deref() != typ {
+            return;
+        }
+
+        let target = match &rule.field {
+            Some(path) => path.split('.').try_fold(content, |v, part| v.get(part)),
+            None => Some(content),
+        };
+        let field = rule.field.as_deref().unwrap_or("content");
+        let fail = |problems: &mut Vec<String>, what: String| {
+            problems.push(match &rule.name {
+                Some(name) => format!("rule `{}`: {}", name, what),
+                None => format!("rule: {}", what),
+            })
+        };
+
+        match (rule.exists, target) {
+            (Some(true), None) => fail(problems, format!("missing `{}`", field)),
+            (Some(false), Some(_)) => fail(problems, format!("unexpected `{}`", field)),
+            _ => {}
+        }
+        if let (Some(re), Some(Value::String(s))) = (&self.regex, target) {
+            if !re.is_match(s) {
+                fail(problems, format!("`{}` doesn't match /{}/", field, re));
+            }
+        }
+        if let (Some(max), Some(v)) = (rule.max_size, target) {
+            if v.to_string().len() > max {
+                fail(
+                    problems,
+                    format!("`{}` is larger than {}", field, units::bytes(max as u64)),
+                );
+            }
+        }
+    }
+}
+
+fn invalid_rule(rule: &LintRule, why: &str) -> Error {
+    let name = rule.name.as_deref().unwrap_or("<unnamed>");
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid lint rule {}: {}", name, why),
+    )
+    .into()
+}
+
+// Check the content of well-known message types against the shape clients
+// are expected to produce, plus any user-defined `rules`, and report what's
+// malformed and by whom.
+pub fn lint_log(log: OffsetLog<u32>, rules: &[LintRule], show: bool) -> Result<(), Error> {
+    let rules = rules
+        .iter()
+        .map(CompiledRule::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut progress = Progress::new(log.end());
+
+    let mut checked: u64 = 0;
+    let mut private: u64 = 0;
+    // type -> problem -> count
+    let mut by_type: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+    let mut by_author: HashMap<String, u64> = HashMap::new();
+
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Checked {} messages", units::count(checked))
+        })?;
+
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        if msg::is_private(&v) {
+            private += 1;
+            continue;
+        }
+        checked += 1;
+
+        let content = match msg::content(&v) {
+            Some(c) => c,
+            None => continue,
+        };
+        let mut problems = check_content(content);
+        for rule in &rules {
+            rule.check(msg::content_type(&v), content, &mut problems);
+        }
+        if problems.is_empty() {
+            continue;
+        }
+
+        let typ = msg::content_type(&v).unwrap_or("<none>");
+        let author = msg::author(&v).unwrap_or("<unknown>");
+        for problem in problems {
+            if show {
+                println!("{}\t{}\t{}: {}", e.offset, uri::show(author), typ, problem);
+            }
+            *by_type
+                .entry(typ.to_string())
+                .or_default()
+                .entry(problem)
+                .or_default() += 1;
+        }
+        *by_author.entry(author.to_string()).or_default() += 1;
+    }
+    progress.finish(&format!("Checked {} messages", units::count(checked)))?;
+
+    println!(
+        "Checked {} public messages ({} private messages skipped)",
+        units::count(checked),
+        units::count(private)
+    );
+    if by_type.is_empty() {
+        println!("No malformed content found.");
+        return Ok(());
+    }
+
+    println!();
+    println!("Malformed content by type:");
+    for (typ, problems) in &by_type {
+        let total: u64 = problems.values().sum();
+        println!("  {:<12} {}", typ, total);
+        for (problem, count) in problems {
+            println!("      {:>8}  {}", count, problem);
+        }
+    }
+
+    let mut authors: Vec<_> = by_author.into_iter().collect();
+    authors.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.cmp(b)));
+    println!();
+    println!("Authors with malformed messages:");
+    for (author, count) in authors {
+        println!("  {:>8}  {}", count, uri::show(&author));
+    }
+    Ok(())
+}
+
+// Returns a description of each way `content` doesn't match the expected
+// shape for its type. Unknown types are only checked for a `type` field.
+fn check_content(content: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let typ = match content.get("type") {
+        Some(Value::String(t)) => t.as_str(),
+        Some(_) => {
+            problems.push("`type` is not a string".to_string());
+            return problems;
+        }
+        None => {
+            problems.push("missing `type` field".to_string());
+            return problems;
+        }
+    };
+
+    match typ {
+        "post" => {
+            require_str(content, "text", None, &mut problems);
+            optional_link(content, "root", '%', &mut problems);
+            optional_link(content, "fork", '%', &mut problems);
+        }
+        "contact" => {
+            require_str(content, "contact", Some('@'), &mut problems);
+            let flags = ["following", "blocking", "flagging"];
+            if !flags.iter().any(|f| content.get(f).is_some()) {
+                problems.push("no `following`, `blocking` or `flagging` field".to_string());
+            }
+            for f in &flags {
+                match content.get(f) {
+                    None | Some(Value::Bool(_)) => {}
+                    // flagging may carry a reason object instead of `true`
+                    Some(Value::Object(_)) if *f == "flagging" => {}
+                    Some(_) => problems.push(format!("`{}` is not a boolean", f)),
+                }
+            }
+        }
+        "vote" => match content.get("vote") {
+            Some(vote @ Value::Object(_)) => {
+                require_str(vote, "link", None, &mut problems);
+                if !vote.get("value").is_some_and(|v| v.is_number()) {
+                    problems.push("`vote.value` is missing or not a number".to_string());
+                }
+            }
+            Some(_) => problems.push("`vote` is not an object".to_string()),
+            None => problems.push("missing `vote` field".to_string()),
+        },
+        "about" => require_str(content, "about", None, &mut problems),
+        _ => {}
+    }
+    problems
+}
+
+fn require_str(obj: &Value, field: &str, sigil: Option<char>, problems: &mut Vec<String>) {
+    match obj.get(field) {
+        Some(Value::String(s)) => {
+            if let Some(c) = sigil {
+                if !s.starts_with(c) {
+                    problems.push(format!("`{}` doesn't start with `{}`", field, c));
+                }
+            }
+        }
+        Some(_) => problems.push(format!("`{}` is not a string", field)),
+        None => problems.push(format!("missing `{}` field", field)),
+    }
+}
+
+fn optional_link(obj: &Value, field: &str, sigil: char, problems: &mut Vec<String>) {
+    match obj.get(field) {
+        None | Some(Value::Null) => {}
+        Some(Value::String(s)) if s.starts_with(sigil) => {}
+        Some(_) => problems.push(format!("`{}` is not a {}-link", field, sigil)),
+    }
+}
@@ -1,8 +1,11 @@
-use std::fs::OpenOptions;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::io::{self, stdin, stdout, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use rayon::prelude::*;
 
 use flumedb::flume_log::{Error, FlumeLog};
@@ -15,14 +18,325 @@ use termion::event::Key;
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 
+mod annotations;
+mod apps;
+mod archive;
+mod backup;
+mod blobs;
+mod chains;
+mod channels;
+mod checksum;
+mod config;
+mod contacts;
+mod diff;
+mod doctor;
+mod export;
+mod filter_cmd;
+mod frame;
+mod frontier;
+mod git_ssb;
+mod grep;
+mod input;
+mod interactions;
+mod key_index;
+mod keyset;
+mod lint;
+mod merge;
+mod monitor;
+mod msg;
+mod names;
+mod output;
+mod peek;
+mod progress;
+mod query;
+mod render;
+mod repair;
+mod report;
+mod resolve;
+mod salvage;
+mod shuffle;
+mod signature;
+mod stats;
+mod sync;
+mod threads;
+mod timeline;
+mod units;
+mod uri;
+mod urls;
+mod validate;
+mod vault;
+mod wizard;
+
+use annotations::Annotations;
+use filter_cmd::FilterCmd;
+use frame::{FrameWriter, RawFrame};
+use progress::Progress;
+
 fn main() -> Result<(), Error> {
     let app_m = App::new("feedrick")
         .version("0.1")
         .author("Sunrise Choir (sunrisechoir.com)")
         .about("ssb flumedb offset log utilities")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .global(true)
+                .help("config file (default: <config dir>/feedrick/config.json)"),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .global(true)
+                .help("Check every frame of input logs before use, reporting the offset of the first damaged one."),
+        )
+        .arg(
+            Arg::with_name("raw_numbers")
+                .long("raw-numbers")
+                .global(true)
+                .help("Print plain counts and byte sizes (2345678, 1503238553 bytes) instead of 2,345,678 and 1.4 GiB, for scripts."),
+        )
+        .arg(
+            Arg::with_name("no_skip_list")
+                .long("no-skip-list")
+                .global(true)
+                .help("Don't leave out the feeds in the config's skip_feeds list."),
+        )
+        .arg(
+            Arg::with_name("uri")
+                .long("uri")
+                .global(true)
+                .help("Print feed ids and message keys in reports and export envelopes as SSB URIs (ssb:feed/classic/...)."),
+        )
+        .subcommand(
+            SubCommand::with_name("annotate")
+                .about("Label or note messages in a log, in a sidecar file next to it")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("offset log file the messages are in"),
+                )
+                .arg(
+                    Arg::with_name("key")
+                        .long("key")
+                        .short("k")
+                        .takes_value(true)
+                        .required_unless("list")
+                        .help("key of the message to annotate"),
+                )
+                .arg(
+                    Arg::with_name("label")
+                        .long("label")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required_unless_one(&["note", "list"])
+                        .help("Add a label (can be repeated)."),
+                )
+                .arg(
+                    Arg::with_name("note")
+                        .long("note")
+                        .takes_value(true)
+                        .help("Add a note."),
+                )
+                .arg(
+                    Arg::with_name("list")
+                        .long("list")
+                        .conflicts_with_all(&["key", "label", "note"])
+                        .help("Print every annotation instead."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("archive")
+                .about("Store many copies of logs, sharing the frames they have in common")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Store a copy of an offset log")
+                        .arg(
+                            Arg::with_name("dir")
+                                .long("dir")
+                                .short("d")
+                                .required(true)
+                                .takes_value(true)
+                                .help("archive directory (created if missing)"),
+                        )
+                        .arg(
+                            Arg::with_name("name")
+                                .long("name")
+                                .short("n")
+                                .takes_value(true)
+                                .help("name for the copy (default: <file name>-<unix time>)"),
+                        )
+                        .arg(
+                            Arg::with_name("LOG")
+                                .help("offset log file to archive")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List the stored copies and the space saved")
+                        .arg(
+                            Arg::with_name("dir")
+                                .long("dir")
+                                .short("d")
+                                .required(true)
+                                .takes_value(true)
+                                .help("archive directory"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("Rebuild a stored copy into a new offset log")
+                        .arg(
+                            Arg::with_name("dir")
+                                .long("dir")
+                                .short("d")
+                                .required(true)
+                                .takes_value(true)
+                                .help("archive directory"),
+                        )
+                        .arg(
+                            Arg::with_name("name")
+                                .long("name")
+                                .short("n")
+                                .required(true)
+                                .takes_value(true)
+                                .help("name of the copy"),
+                        )
+                        .arg(
+                            Arg::with_name("out")
+                                .long("out")
+                                .short("o")
+                                .required(true)
+                                .takes_value(true)
+                                .help("destination path"),
+                        )
+                        .arg(
+                            Arg::with_name("overwrite")
+                                .long("overwrite")
+                                .help("Overwrite output file, if it exists."),
+                        )
+                        .arg(
+                            Arg::with_name("backup")
+                                .long("backup")
+                                .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Rebuild a log from a full backup and the deltas made after it")
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("destination path"),
+                )
+                .arg(
+                    Arg::with_name("BACKUP")
+                        .help("backup files, oldest first")
+                        .required(true)
+                        .multiple(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("repair")
+                .about("Copy the entries of a log up to the first broken frame, or cut the log there")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("offset log file with a corrupted tail"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .takes_value(true)
+                        .required_unless("in_place")
+                        .help("destination path"),
+                )
+                .arg(
+                    Arg::with_name("in_place")
+                        .long("in-place")
+                        .conflicts_with("out")
+                        .help("Replace the input log with the repaired copy instead, moving the original to `<in>.<unix time>.bak`."),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("salvage")
+                .about("Recover messages from a log with destroyed framing, by scanning for message JSON")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("damaged offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("destination path"),
+                )
+                .arg(
+                    Arg::with_name("keep_unverified")
+                        .long("keep-unverified")
+                        .help("Also keep messages whose signature or key doesn't verify."),
+                )
+                .arg(
+                    Arg::with_name("salvage_io")
+                        .long("salvage-io")
+                        .help("Skip parts of the file that can't be read (eg. bad sectors) instead of giving up, listing the skipped ranges."),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("sort")
-                .about("Copy all the feeds and sort by asserted time")
+                .about("Copy all the feeds and sort by asserted time, or shuffle them")
                 .arg(
                     Arg::with_name("in")
                         .long("in")
@@ -43,11 +357,67 @@ fn main() -> Result<(), Error> {
                     Arg::with_name("overwrite")
                         .long("overwrite")
                         .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                )
+                .arg(
+                    Arg::with_name("shuffle")
+                        .long("shuffle")
+                        .requires("seed")
+                        .help("Put the messages in a random order instead, keeping each feed's in sequence order."),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .takes_value(true)
+                        .requires("shuffle")
+                        .help("Seed for --shuffle; the same seed always gives the same order."),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("extract")
-                .about("Copy the feed for a single id into a separate file.")
+            SubCommand::with_name("authors")
+                .about("List every feed in the log, with message counts, sequence range and latest activity")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .takes_value(true)
+                        .possible_values(&["count", "name"])
+                        .default_value("count")
+                        .help("by message count (most first) or by feed id"),
+                )
+                .arg(
+                    Arg::with_name("resolve_names")
+                        .long("resolve-names")
+                        .help("Show the name each feed gave itself in its about messages."),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Output the list as JSON ({ authors })."),
+                )
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .takes_value(true)
+                        .value_name("path")
+                        .conflicts_with("json")
+                        .help("Render the --json data with this minijinja (Jinja2) template instead."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("backup")
+                .about("Copy the messages that are newer than a previous backup's manifest")
                 .arg(
                     Arg::with_name("in")
                         .long("in")
@@ -62,131 +432,2582 @@ fn main() -> Result<(), Error> {
                         .short("o")
                         .required(true)
                         .takes_value(true)
-                        .help("destination path"),
+                        .help("destination path; the manifest is written to <out>.json"),
                 )
                 .arg(
-                    Arg::with_name("id")
-                        .long("feed")
-                        .short("f")
+                    Arg::with_name("since")
+                        .long("since-manifest")
+                        .takes_value(true)
+                        .help("manifest of the previous backup (default: back up everything)"),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output files, if they exist."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("blobs")
+                .about("List the blob ids referenced from messages, with how many messages reference each")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
                         .required(true)
                         .takes_value(true)
-                        .help("feed (user) id (eg. \"@N/vWpVVdD...\""),
+                        .help("source offset log file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("channels")
+                .about("Count the messages and authors in each channel, by channel field or hashtag")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("top")
+                        .long("top")
+                        .short("n")
+                        .takes_value(true)
+                        .default_value("50")
+                        .help("number of channels to list"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("checksum")
+                .about("Write a log's SHA-256 digest, and optionally each entry's, to a sidecar file for checking archives later")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("checksums file to write, eg. log.sums"),
+                )
+                .arg(
+                    Arg::with_name("per_entry")
+                        .long("per-entry")
+                        .help("Also write each entry's digest, so damage can be pinned to entries."),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("contacts")
+                .about("Write the follow/block graph: each author's latest relation to each feed, from contact messages")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .takes_value(true)
+                        .default_value("-")
+                        .help("file to write, or - for stdout"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["json", "follows", "blocks"])
+                        .default_value("json")
+                        .help("JSON of every relation, or adjacency lists of follows or blocks (an author and the feeds they follow, per line)"),
                 )
                 .arg(
                     Arg::with_name("overwrite")
                         .long("overwrite")
                         .help("Overwrite output file, if it exists."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("count")
+                .about("Count a log's entries, deleted ones and those that aren't JSON, from the framing alone")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
                 )
                 .arg(
-                    Arg::with_name("invert")
-                        .long("invert")
-                        .help("Output a log file containing all feeds *but* the specified id."),
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the counts as a line of JSON."),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("view")
-                .about("View a flumedb offset log file")
+            SubCommand::with_name("diff")
+                .about("Compare two logs: per feed, the messages only in one of them, and those whose bytes differ")
                 .arg(
-                    Arg::with_name("FILE")
-                        .help("offset log file to view")
+                    Arg::with_name("a")
+                        .long("a")
+                        .short("a")
+                        .required(true)
+                        .takes_value(true)
+                        .help("first offset log file"),
+                )
+                .arg(
+                    Arg::with_name("b")
+                        .long("b")
+                        .short("b")
                         .required(true)
+                        .takes_value(true)
+                        .help("second offset log file"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("output format"),
+                )
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .takes_value(true)
+                        .value_name("path")
+                        .help("Render the json data with this minijinja (Jinja2) template instead."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Inspect an ssb directory and suggest fixes for problems found")
+                .arg(
+                    Arg::with_name("DIR")
+                        .help("ssb directory (default: ~/.ssb)")
                         .index(1),
                 ),
         )
-        .get_matches();
+        .subcommand(
+            SubCommand::with_name("extract")
+                .about("Copy the feed for a single id, or the messages in some channels or mentioning a feed, into a separate file.")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("destination path"),
+                )
+                .arg(
+                    Arg::with_name("id")
+                        .long("feed")
+                        .short("f")
+                        .required_unless_one(&["filter_cmd", "channel", "mentions", "keys_file", "as_of"])
+                        .takes_value(true)
+                        .help("feed (user) id (eg. \"@N/vWpVVdD...\""),
+                )
+                .arg(
+                    Arg::with_name("channel")
+                        .long("channel")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Only copy messages in this channel (by their channel field or a hashtag), from any author. Repeatable."),
+                )
+                .arg(
+                    Arg::with_name("mentions")
+                        .long("mentions")
+                        .takes_value(true)
+                        .value_name("id")
+                        .help("Only copy messages that mention this feed (in their mentions or text), from any author."),
+                )
+                .arg(
+                    Arg::with_name("keys_file")
+                        .long("keys-file")
+                        .takes_value(true)
+                        .help("Only copy messages whose key is listed in this file (one per line), from any author."),
+                )
+                .arg(
+                    Arg::with_name("as_of")
+                        .long("as-of")
+                        .takes_value(true)
+                        .value_name("date")
+                        .help("Only copy messages from before this time (YYYY-MM-DD[THH:MM[:SS]] UTC, or ms since the epoch), from any author. Each feed stops at its first later message, so none are left with gaps."),
+                )
+                .arg(
+                    Arg::with_name("received")
+                        .long("received")
+                        .requires("as_of")
+                        .help("Use the time messages were received for --as-of, instead of the time their authors claim."),
+                )
+                .arg(
+                    Arg::with_name("filter_cmd")
+                        .long("filter-cmd")
+                        .takes_value(true)
+                        .help("Shell command that reads messages as NDJSON on stdin and answers each line with `true` (keep) or `false`."),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                )
+                .arg(
+                    Arg::with_name("invert")
+                        .long("invert")
+                        .requires("id")
+                        .help("Output a log file containing all feeds *but* the specified id."),
+                )
+                .arg(
+                    Arg::with_name("validate")
+                        .long("validate")
+                        .help("Check the output afterwards: signatures, and that each feed's messages follow on from each other. Reports the first that doesn't."),
+                )
+                .arg(
+                    Arg::with_name("trim_to_valid")
+                        .long("trim-to-valid")
+                        .requires("validate")
+                        .help("Cut the output before the first message that doesn't validate, so what's left is usable."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Write every message as a line of JSON (NDJSON)")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("NDJSON file to write, or - for stdout"),
+                )
+                .arg(
+                    Arg::with_name("value")
+                        .long("value")
+                        .help("Only write each message's signed value, without the flume envelope."),
+                )
+                .arg(
+                    Arg::with_name("since_state")
+                        .long("since-state")
+                        .takes_value(true)
+                        .value_name("file")
+                        .help("Only export the messages appended since the run that last updated this state file, then update it"),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("forks")
+                .about("Find forked feeds: two messages by one author with the same sequence number or previous")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("frontier")
+                .about("Write every feed's latest sequence number as a JSON map (an EBT-style vector clock)")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .takes_value(true)
+                        .default_value("-")
+                        .help("JSON file to write, or - for stdout"),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compare-frontier")
+                .about("Compare two frontiers (from `frontier` or an sbot's EBT clock): which feeds are ahead where, and by how many messages")
+                .arg(
+                    Arg::with_name("a")
+                        .long("a")
+                        .short("a")
+                        .required(true)
+                        .takes_value(true)
+                        .help("first frontier JSON file"),
+                )
+                .arg(
+                    Arg::with_name("b")
+                        .long("b")
+                        .short("b")
+                        .required(true)
+                        .takes_value(true)
+                        .help("second frontier JSON file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fsck")
+                .about("Check the framing of an offset log, reporting the offset of the first corruption")
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("offset log file to check")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("gaps")
+                .about("List the sequence numbers each feed has and is missing, without checking previous-links")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("Also list the feeds that have no gaps."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Print one message, found by key, feed and sequence number, or offset")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("key")
+                        .long("key")
+                        .short("k")
+                        .takes_value(true)
+                        .required_unless_one(&["feed", "offset"])
+                        .conflicts_with_all(&["feed", "offset"])
+                        .help("key of the message (eg. \"%Xz...=.sha256\")"),
+                )
+                .arg(
+                    Arg::with_name("feed")
+                        .long("feed")
+                        .short("f")
+                        .takes_value(true)
+                        .requires("seq")
+                        .conflicts_with("offset")
+                        .help("author of the message, with --seq"),
+                )
+                .arg(
+                    Arg::with_name("seq")
+                        .long("seq")
+                        .takes_value(true)
+                        .requires("feed")
+                        .help("sequence number of the message in --feed"),
+                )
+                .arg(
+                    Arg::with_name("offset")
+                        .long("offset")
+                        .takes_value(true)
+                        .help("byte offset of the entry in the log"),
+                )
+                .arg(
+                    Arg::with_name("rendered")
+                        .long("rendered")
+                        .help("Render well-known message types as text instead of showing the JSON."),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .conflicts_with("rendered")
+                        .help("Print `{ \"offset\", \"entry\" }` on one line, for scripts."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("git-ssb")
+                .about("Summarize the git-ssb repos in a log, or extract one repo's messages")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("repo")
+                        .long("repo")
+                        .takes_value(true)
+                        .requires("out")
+                        .help("repo id (the key of its git-repo message) to extract"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .takes_value(true)
+                        .requires("repo")
+                        .help("destination path for the repo's messages"),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("grep")
+                .about("Print the entries matching a regex as NDJSON, with their offsets")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("pattern")
+                        .long("pattern")
+                        .short("e")
+                        .required(true)
+                        .takes_value(true)
+                        .help("regular expression, matched against each entry's JSON"),
+                )
+                .arg(
+                    Arg::with_name("field")
+                        .long("field")
+                        .takes_value(true)
+                        .help("Match against this field only, as a dotted path (eg. value.content.text)."),
+                )
+                .arg(
+                    Arg::with_name("preview")
+                        .long("preview")
+                        .help("Print each match as one line, its offset and a preview of its type and text, instead of JSON."),
+                )
+                .arg(
+                    Arg::with_name("preview_len")
+                        .long("preview-len")
+                        .takes_value(true)
+                        .value_name("chars")
+                        .help("Cut each previewed field to this many characters (default 80). Implies --preview."),
+                )
+                .arg(
+                    Arg::with_name("preview_field")
+                        .long("preview-field")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("path")
+                        .help("Preview this field, as a dotted path, instead of the type and text. Repeatable; implies --preview."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("head")
+                .about("Print the first entries of a log, with their offsets")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("lines")
+                        .short("n")
+                        .long("lines")
+                        .takes_value(true)
+                        .default_value("10")
+                        .help("how many entries to print"),
+                )
+                .arg(
+                    Arg::with_name("raw")
+                        .long("raw")
+                        .help("Show entries as JSON instead of rendering well-known message types."),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .conflicts_with("raw")
+                        .help("Print NDJSON, `{ \"offset\", \"entry\" }` per line, for scripts."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("interactions")
+                .about("Count replies, mentions and votes between pairs of authors")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("top")
+                        .long("top")
+                        .short("n")
+                        .takes_value(true)
+                        .default_value("50")
+                        .help("number of pairs to list"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Output the whole weighted graph as JSON ({ nodes, edges })."),
+                )
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .takes_value(true)
+                        .value_name("path")
+                        .conflicts_with("json")
+                        .help("Render the --json data with this minijinja (Jinja2) template instead."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("key-index")
+                .about("Write a key,author,sequence CSV row for every message")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("destination path, or - for stdout"),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("keys")
+                .about("Compare a file of message keys (one per line) with a log")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("present")
+                        .about("Print the keys from the file that are in the log")
+                        .arg(
+                            Arg::with_name("in")
+                                .long("in")
+                                .short("i")
+                                .required(true)
+                                .takes_value(true)
+                                .help("source offset log file"),
+                        )
+                        .arg(
+                            Arg::with_name("keys_file")
+                                .long("keys-file")
+                                .short("k")
+                                .required(true)
+                                .takes_value(true)
+                                .help("file of message keys, one per line"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("missing")
+                        .about("Print the keys from the file that aren't in the log")
+                        .arg(
+                            Arg::with_name("in")
+                                .long("in")
+                                .short("i")
+                                .required(true)
+                                .takes_value(true)
+                                .help("source offset log file"),
+                        )
+                        .arg(
+                            Arg::with_name("keys_file")
+                                .long("keys-file")
+                                .short("k")
+                                .required(true)
+                                .takes_value(true)
+                                .help("file of message keys, one per line"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Check the content of well-known message types for malformed fields")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("show")
+                        .long("show")
+                        .help("Print every malformed message (offset, author, problem)."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("names")
+                .about("List the names and images feeds gave themselves in about messages, as NDJSON")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("others")
+                        .long("others")
+                        .help("Also list the names other feeds gave each feed."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("query")
+                .about("Run a jq-style query against each entry, printing the results as NDJSON")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("query")
+                        .required(true)
+                        .help("eg. 'select .value.content.type == \"post\" | .value.author'"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("resolve")
+                .about("Print the latest state of gatherings, blog posts and profiles as NDJSON, with updates and tombstones applied")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("type")
+                        .long("type")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .possible_values(resolve::KINDS)
+                        .help("Only resolve records of this type (can be repeated). Defaults to all."),
+                )
+                .arg(
+                    Arg::with_name("tombstoned")
+                        .long("tombstoned")
+                        .help("Also print deleted records, with their tombstone."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compact")
+                .about("Copy a log without its deleted (zero-filled) entries")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("destination path"),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("dedupe")
+                .about("Copy a log without repeated messages, keeping the first copy of each key")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("destination path"),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("merge")
+                .about("Combine several offset logs into one, skipping messages already copied from an earlier log")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("source offset log file (repeatable, copied in the order given)"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("destination path"),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("monitor")
+                .about("Alert when feeds in a log are behind an expected frontier")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("offset log file to watch"),
+                )
+                .arg(
+                    Arg::with_name("expect")
+                        .long("expect")
+                        .required_unless_one(&["alert_on_silence", "alert_on_rate"])
+                        .takes_value(true)
+                        .help("JSON file mapping feed ids to expected latest sequence numbers"),
+                )
+                .arg(
+                    Arg::with_name("interval")
+                        .long("interval")
+                        .takes_value(true)
+                        .help("Keep checking every <interval> seconds, alerting on feeds that stop advancing (default with alarms: 10)"),
+                )
+                .arg(
+                    Arg::with_name("alert_on_silence")
+                        .long("alert-on-silence")
+                        .takes_value(true)
+                        .help("Alert when the log hasn't grown for this long (eg. 90s, 30m, 1h, 2d)"),
+                )
+                .arg(
+                    Arg::with_name("alert_on_rate")
+                        .long("alert-on-rate")
+                        .takes_value(true)
+                        .help("Alert when the log grows faster than this many messages per unit of time (eg. 1000/min)"),
+                )
+                .arg(
+                    Arg::with_name("alert_cmd")
+                        .long("alert-cmd")
+                        .takes_value(true)
+                        .help("Shell command to run for every alert, with the alert text in $FEEDRICK_ALERT"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Summarize a log: entries, authors, bytes, content types and how recently each feed was active")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("received")
+                        .long("received")
+                        .help("Use the time messages were received instead of their asserted time."),
+                )
+                .arg(
+                    Arg::with_name("list")
+                        .long("list")
+                        .help("List every feed, most recently active first."),
+                )
+                .arg(
+                    Arg::with_name("apps")
+                        .long("apps")
+                        .help("Also summarize the messages of known apps (gatherings, chess, bookclub)."),
+                )
+                .arg(
+                    Arg::with_name("resolve_names")
+                        .long("resolve-names")
+                        .conflicts_with_all(&["compare", "timeline"])
+                        .help("Show the name each of the top authors gave itself in its about messages."),
+                )
+                .arg(
+                    Arg::with_name("compare")
+                        .long("compare")
+                        .takes_value(true)
+                        .help("Compare with this log (\"after\", with --in as \"before\") instead"),
+                )
+                .arg(
+                    Arg::with_name("top")
+                        .long("top")
+                        .short("n")
+                        .takes_value(true)
+                        .default_value("20")
+                        .help("number of authors to list (the largest, or with --compare, the most changed)"),
+                )
+                .arg(
+                    Arg::with_name("timeline")
+                        .long("timeline")
+                        .takes_value(true)
+                        .value_name("bucket")
+                        .conflicts_with("compare")
+                        .help("List messages, bytes and active feeds over time instead, by `week` (ISO), `month`, `quarter` or a duration like 6h or 10d"),
+                )
+                .arg(
+                    Arg::with_name("tz")
+                        .long("tz")
+                        .takes_value(true)
+                        .requires("timeline")
+                        .help("UTC offset the timeline's buckets start in, like +02:00 or (as --tz=-05:00) a negative one; default UTC, and daylight saving time isn't applied"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .conflicts_with_all(&["compare", "timeline", "apps"])
+                        .help("Output the summary and activity as JSON."),
+                )
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .takes_value(true)
+                        .value_name("path")
+                        .conflicts_with_all(&["json", "compare", "timeline", "apps"])
+                        .help("Render the --json data with this minijinja (Jinja2) template instead."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sync")
+                .about("Append the messages of one log that another is missing, in place")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .required(true)
+                        .takes_value(true)
+                        .help("offset log to copy missing messages from"),
+                )
+                .arg(
+                    Arg::with_name("into")
+                        .long("into")
+                        .required(true)
+                        .takes_value(true)
+                        .help("offset log to append them to"),
+                )
+                .arg(
+                    Arg::with_name("dry_run")
+                        .long("dry-run")
+                        .help("Only report what would be appended."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tail")
+                .about("Print the last entries of a log, with their offsets, and optionally follow it")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("lines")
+                        .short("n")
+                        .long("lines")
+                        .takes_value(true)
+                        .default_value("10")
+                        .help("how many entries to print"),
+                )
+                .arg(
+                    Arg::with_name("follow")
+                        .long("follow")
+                        .short("f")
+                        .help("Keep printing entries as they're appended, until interrupted."),
+                )
+                .arg(
+                    Arg::with_name("raw")
+                        .long("raw")
+                        .help("Show entries as JSON instead of rendering well-known message types."),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .conflicts_with("raw")
+                        .help("Print NDJSON, `{ \"offset\", \"entry\" }` per line, for scripts."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("slice")
+                .about("Copy a range of a log's entries, by byte offset or entry number")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("destination path"),
+                )
+                .arg(
+                    Arg::with_name("from_offset")
+                        .long("from-offset")
+                        .takes_value(true)
+                        .required_unless_one(&["to_offset", "from_seq", "to_seq"])
+                        .help("Copy the entries that start at or after this byte offset."),
+                )
+                .arg(
+                    Arg::with_name("to_offset")
+                        .long("to-offset")
+                        .takes_value(true)
+                        .help("Copy the entries that start before this byte offset."),
+                )
+                .arg(
+                    Arg::with_name("from_seq")
+                        .long("from-seq")
+                        .takes_value(true)
+                        .help("Copy from this entry on, counting the log's entries from 0 (not a feed's sequence numbers)."),
+                )
+                .arg(
+                    Arg::with_name("to_seq")
+                        .long("to-seq")
+                        .takes_value(true)
+                        .help("Copy the entries before this one, counting from 0."),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("truncate")
+                .about("Copy the first part of a log: the entries before a byte offset, or the first N")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .required(true)
+                        .takes_value(true)
+                        .help("destination path"),
+                )
+                .arg(
+                    Arg::with_name("at_offset")
+                        .long("at-offset")
+                        .takes_value(true)
+                        .required_unless("count")
+                        .help("Copy the entries that start before this byte offset."),
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .long("count")
+                        .short("n")
+                        .takes_value(true)
+                        .conflicts_with("at_offset")
+                        .help("Copy the first N entries."),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite output file, if it exists."),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .long("backup")
+                        .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("types")
+                .about("Count the messages and bytes of each content type, encrypted messages as <private>")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .takes_value(true)
+                        .possible_values(&["bytes", "count"])
+                        .default_value("bytes")
+                        .help("by total bytes or by message count, largest first"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Output the counts as JSON ({ types, messages, bytes, unreadable })."),
+                )
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .takes_value(true)
+                        .value_name("path")
+                        .conflicts_with("json")
+                        .help("Render the --json data with this minijinja (Jinja2) template instead."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("urls")
+                .about("List the external URLs linked from posts, with counts and when each was first seen")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("top")
+                        .long("top")
+                        .short("n")
+                        .takes_value(true)
+                        .default_value("50")
+                        .help("number of URLs to list"),
+                )
+                .arg(
+                    Arg::with_name("csv")
+                        .long("csv")
+                        .takes_value(true)
+                        .value_name("path")
+                        .help("Write every URL as a url,count,first_seen,first_key CSV row to this path (- for stdout) instead."),
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                        .long("overwrite")
+                        .help("Overwrite the CSV file, if it exists."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("vault")
+                .about("Keep a deduplicated archive of messages from many logs")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add the messages of offset logs that aren't in the vault yet")
+                        .arg(
+                            Arg::with_name("dir")
+                                .long("dir")
+                                .short("d")
+                                .required(true)
+                                .takes_value(true)
+                                .help("vault directory (created if missing)"),
+                        )
+                        .arg(
+                            Arg::with_name("LOG")
+                                .help("offset log files to add")
+                                .required(true)
+                                .multiple(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("gc")
+                        .about("Drop messages from the vault according to retention rules")
+                        .arg(
+                            Arg::with_name("dir")
+                                .long("dir")
+                                .short("d")
+                                .required(true)
+                                .takes_value(true)
+                                .help("vault directory"),
+                        )
+                        .arg(
+                            Arg::with_name("keep_years")
+                                .long("keep-years")
+                                .takes_value(true)
+                                .help("drop messages older than this many years"),
+                        )
+                        .arg(
+                            Arg::with_name("drop_votes_older_than")
+                                .long("drop-votes-older-than")
+                                .takes_value(true)
+                                .help("drop votes older than this many days"),
+                        )
+                        .arg(
+                            Arg::with_name("keep_followed_by")
+                                .long("keep-followed-by")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .help("keep everything by this feed and the feeds it follows (may be repeated)"),
+                        )
+                        .arg(
+                            Arg::with_name("dry_run")
+                                .long("dry-run")
+                                .help("Only report what would be dropped."),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("materialize")
+                        .about("Write the vault's messages, optionally filtered, to a new offset log")
+                        .arg(
+                            Arg::with_name("dir")
+                                .long("dir")
+                                .short("d")
+                                .required(true)
+                                .takes_value(true)
+                                .help("vault directory"),
+                        )
+                        .arg(
+                            Arg::with_name("out")
+                                .long("out")
+                                .short("o")
+                                .required(true)
+                                .takes_value(true)
+                                .help("destination path"),
+                        )
+                        .arg(
+                            Arg::with_name("feed")
+                                .long("feed")
+                                .short("f")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .help("only messages by this feed id (may be repeated)"),
+                        )
+                        .arg(
+                            Arg::with_name("type")
+                                .long("type")
+                                .short("t")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .help("only messages of this content type (may be repeated)"),
+                        )
+                        .arg(
+                            Arg::with_name("root")
+                                .long("root")
+                                .takes_value(true)
+                                .help("also include feeds within --hops follows of this feed id (with just --hops, the feed in ~/.ssb/secret)"),
+                        )
+                        .arg(
+                            Arg::with_name("hops")
+                                .long("hops")
+                                .takes_value(true)
+                                .help("follow distance from --root to include (default 2)"),
+                        )
+                        .arg(
+                            Arg::with_name("since_days")
+                                .long("since-days")
+                                .takes_value(true)
+                                .help("only messages from the last this many days, except --priority-type ones"),
+                        )
+                        .arg(
+                            Arg::with_name("priority_type")
+                                .long("priority-type")
+                                .takes_value(true)
+                                .multiple(true)
+                                .use_delimiter(true)
+                                .default_value("contact,about")
+                                .help("content types included however old they are, eg. the follow graph and names"),
+                        )
+                        .arg(
+                            Arg::with_name("overwrite")
+                                .long("overwrite")
+                                .help("Overwrite output file, if it exists."),
+                        )
+                        .arg(
+                            Arg::with_name("backup")
+                                .long("backup")
+                                .help("Move an existing output file to `<out>.<unix time>.bak` first."),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Check every feed's chain of previous-links across the whole log")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("source offset log file"),
+                )
+                .arg(
+                    Arg::with_name("partial")
+                        .long("partial")
+                        .help("Just list the feeds whose earliest message isn't #1, with how much history is missing."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-checksums")
+                .about("Check a log against a checksums file written by `checksum`, listing damaged entries")
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .short("i")
+                        .required(true)
+                        .takes_value(true)
+                        .help("offset log file to check"),
+                )
+                .arg(
+                    Arg::with_name("sums")
+                        .long("sums")
+                        .required(true)
+                        .takes_value(true)
+                        .help("checksums file written by `checksum`"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("view")
+                .about("View a flumedb offset log file")
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("offset log file to view")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("raw")
+                        .long("raw")
+                        .help("Show entries as JSON instead of rendering well-known message types."),
+                )
+                .arg(
+                    Arg::with_name("markdown")
+                        .long("markdown")
+                        .conflicts_with("raw")
+                        .help("Render the markdown of posts (emphasis, lists, quotes, links) and name mentioned feeds."),
+                )
+                .arg(
+                    Arg::with_name("threads_feed")
+                        .long("threads-feed")
+                        .takes_value(true)
+                        .value_name("id")
+                        .conflicts_with("raw")
+                        .help("Browse the conversations this feed posted in, grouped into threads."),
+                )
+                .arg(
+                    Arg::with_name("threads_channel")
+                        .long("threads-channel")
+                        .takes_value(true)
+                        .value_name("channel")
+                        .conflicts_with_all(&["raw", "threads_feed"])
+                        .help("Browse the conversations in a channel, grouped into threads."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("wizard")
+                .about("Walk through common tasks (backing up your feed, checking your log) step by step"),
+        )
+        .get_matches();
+
+    input::set_strict(app_m.is_present("strict"));
+    units::set_raw(app_m.is_present("raw_numbers"));
+    uri::set_uri(app_m.is_present("uri"));
+
+    match app_m.subcommand() {
+        ("annotate", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let mut annotations = Annotations::load(in_path)?;
+            if sub_m.is_present("list") {
+                for (key, a) in annotations.iter() {
+                    println!("{}  {}", uri::show(key), a.summary());
+                }
+                return Ok(());
+            }
+            let labels: Vec<&str> = sub_m.values_of("label").into_iter().flatten().collect();
+            annotations.add(
+                &id_arg(sub_m, "key").unwrap(),
+                &labels,
+                sub_m.value_of("note"),
+            );
+            annotations.save()?;
+            eprintln!("Saved to {}", annotations.path().display());
+            Ok(())
+        }
+        ("archive", Some(archive_m)) => match archive_m.subcommand() {
+            ("add", Some(sub_m)) => {
+                let in_path = sub_m.value_of("LOG").unwrap();
+                let name = match sub_m.value_of("name") {
+                    Some(n) => n.to_string(),
+                    None => {
+                        let file = Path::new(in_path).file_name().unwrap_or_default();
+                        let secs = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .unwrap_or(0);
+                        format!("{}-{}", file.to_string_lossy(), secs)
+                    }
+                };
+                // Only for its diagnostics; the archive reads the frames itself.
+                input::open_log(in_path)?;
+                let mut archive =
+                    archive::Archive::open(Path::new(sub_m.value_of("dir").unwrap()))?;
+                archive.add(in_path, &name)
+            }
+            ("list", Some(sub_m)) => {
+                let archive = archive::Archive::open(Path::new(sub_m.value_of("dir").unwrap()))?;
+                for (name, copy) in archive.copies()? {
+                    println!(
+                        "{}\t{}\t{} frames\tadded {}\tfrom {}",
+                        name,
+                        units::bytes(copy.size),
+                        units::count(copy.frames),
+                        copy.added,
+                        copy.source
+                    );
+                }
+                archive.summary()
+            }
+            ("restore", Some(sub_m)) => {
+                let out_path = sub_m.value_of("out").unwrap();
+                let name = sub_m.value_of("name").unwrap();
+                let archive = archive::Archive::open(Path::new(sub_m.value_of("dir").unwrap()))?;
+                let copy = match archive.copy(name)? {
+                    Some(c) => c,
+                    None => {
+                        eprintln!("The archive has no copy named `{}`.", name);
+                        process::exit(1);
+                    }
+                };
+                if !output::prepare(
+                    out_path,
+                    &[],
+                    sub_m.is_present("overwrite"),
+                    sub_m.is_present("backup"),
+                )? {
+                    return Ok(());
+                }
+                if !output::check_space(out_path, copy.size, true)? {
+                    return Ok(());
+                }
+                let out_log = output::create_log(out_path)?;
+                archive.restore(name, &copy, out_log)
+            }
+            _ => unreachable!(),
+        },
+        ("authors", Some(sub_m)) => {
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            let scan = stats::scan_feeds(&log, &mut [], &skip_list(&app_m)?)?;
+            let names = match sub_m.is_present("resolve_names") {
+                true => Some(names::Names::scan(&log)?),
+                false => None,
+            };
+            let format =
+                report::Format::from_args(sub_m.is_present("json"), sub_m.value_of("template"))?;
+            stats::authors_report(
+                &scan.feeds,
+                sub_m.value_of("sort") == Some("name"),
+                names.as_ref(),
+                &format,
+            )
+        }
+        ("backup", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+            let manifest_path = format!("{}.json", out_path);
+            let overwrite = sub_m.is_present("overwrite");
+
+            let since = match sub_m.value_of("since") {
+                Some(p) => frontier::read(p)?,
+                None => frontier::Frontier::new(),
+            };
+            if !output::prepare(out_path, &[in_path], overwrite, false)?
+                || !output::prepare(&manifest_path, &[in_path], overwrite, false)?
+            {
+                return Ok(());
+            }
+            let in_log = input::open_log(in_path)?;
+            if !output::check_space(out_path, in_log.end(), false)? {
+                return Ok(());
+            }
+            let out_log = output::create_log(out_path)?;
 
-    match app_m.subcommand() {
+            let manifest = backup::backup(in_log, out_log, &since)?;
+            frontier::write(&manifest_path, &manifest)?;
+            println!("Wrote manifest {}", manifest_path);
+            Ok(())
+        }
+        ("restore", Some(sub_m)) => {
+            let out_path = sub_m.value_of("out").unwrap();
+            let in_paths: Vec<&str> = sub_m.values_of("BACKUP").unwrap().collect();
+
+            if !output::prepare(
+                out_path,
+                &in_paths,
+                sub_m.is_present("overwrite"),
+                sub_m.is_present("backup"),
+            )? {
+                return Ok(());
+            }
+            let backups = in_paths
+                .iter()
+                .map(|p| input::open_log(p))
+                .collect::<Result<Vec<_>, _>>()?;
+            let total = backups.iter().map(|l| l.end()).sum();
+            if !output::check_space(out_path, total, false)? {
+                return Ok(());
+            }
+            let out_log = output::create_log(out_path)?;
+            backup::restore(backups, out_log)
+        }
+        ("names", Some(sub_m)) => {
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            names::Names::scan(&log)?.write(sub_m.is_present("others"))
+        }
+        ("query", Some(sub_m)) => {
+            let q = query::Query::parse(sub_m.value_of("query").unwrap()).unwrap_or_else(|e| {
+                eprintln!("Invalid query: {}", e);
+                process::exit(1);
+            });
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            query::query(&log, &q)
+        }
+        ("resolve", Some(sub_m)) => {
+            let kinds: Vec<&str> = match sub_m.values_of("type") {
+                Some(types) => types.collect(),
+                None => resolve::KINDS.to_vec(),
+            };
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            resolve::resolve(&log, &kinds, sub_m.is_present("tombstoned"))
+        }
+        ("compact", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+
+            if !output::prepare(
+                out_path,
+                &[in_path],
+                sub_m.is_present("overwrite"),
+                sub_m.is_present("backup"),
+            )? {
+                return Ok(());
+            }
+            let in_log = input::open_log(in_path)?;
+            let in_size = in_log.end();
+            if !output::check_space(out_path, in_size, false)? {
+                return Ok(());
+            }
+            let mut out = FrameWriter::new(output::create_file(out_path)?);
+            // ssb-db deletes a message by overwriting its data with zeros.
+            copy_frames(in_path, &mut out, in_size, |_, f| Some(!f.is_zeroed()))?;
+            let out_size = out.finish()?;
+            println!(
+                "Reclaimed {} ({} -> {}).",
+                units::bytes(in_size - out_size),
+                units::bytes(in_size),
+                units::bytes(out_size)
+            );
+            Ok(())
+        }
+        ("dedupe", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+
+            if !output::prepare(
+                out_path,
+                &[in_path],
+                sub_m.is_present("overwrite"),
+                sub_m.is_present("backup"),
+            )? {
+                return Ok(());
+            }
+            let in_log = input::open_log(in_path)?;
+            if !output::check_space(out_path, in_log.end(), false)? {
+                return Ok(());
+            }
+            let mut out_log = output::create_log(out_path)?;
+            let copied = merge::copy_unique(&[in_log], &mut out_log, "Log")?;
+            println!(
+                "Kept {} entries, removed {} duplicate messages.",
+                units::count(copied.messages),
+                units::count(copied.total_duplicates())
+            );
+            let mut authors: Vec<_> = copied.duplicates.iter().collect();
+            authors.sort_by_key(|(id, n)| (std::cmp::Reverse(**n), *id));
+            for (id, n) in authors {
+                println!("  {:>8}  {}", units::count(*n), uri::show(id));
+            }
+            Ok(())
+        }
+        ("merge", Some(sub_m)) => {
+            let out_path = sub_m.value_of("out").unwrap();
+            let in_paths: Vec<&str> = sub_m.values_of("in").unwrap().collect();
+
+            if !output::prepare(
+                out_path,
+                &in_paths,
+                sub_m.is_present("overwrite"),
+                sub_m.is_present("backup"),
+            )? {
+                return Ok(());
+            }
+            let logs = in_paths
+                .iter()
+                .map(|p| input::open_log(p))
+                .collect::<Result<Vec<_>, _>>()?;
+            let total = logs.iter().map(|l| l.end()).sum();
+            if !output::check_space(out_path, total, false)? {
+                return Ok(());
+            }
+            let mut out_log = output::create_log(out_path)?;
+            let copied = merge::copy_unique(&logs, &mut out_log, "Log")?;
+            println!(
+                "Merged {} messages from {} logs ({} duplicates skipped).",
+                units::count(copied.messages),
+                logs.len(),
+                units::count(copied.total_duplicates())
+            );
+            Ok(())
+        }
+        ("blobs", Some(sub_m)) => {
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            blobs::blobs(&log)
+        }
+        ("channels", Some(sub_m)) => {
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            channels::channels(&log, parse_number(sub_m, "top"))
+        }
+        ("checksum", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+            if !output::prepare(out_path, &[in_path], sub_m.is_present("overwrite"), false)? {
+                return Ok(());
+            }
+            // Only for its diagnostics; the checksums are over the raw bytes.
+            input::open_log(in_path)?;
+            checksum::write(in_path, out_path, sub_m.is_present("per_entry"))
+        }
+        ("contacts", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+            if out_path != "-"
+                && !output::prepare(out_path, &[in_path], sub_m.is_present("overwrite"), false)?
+            {
+                return Ok(());
+            }
+            let follows = contacts::Follows::scan(&input::open_log(in_path)?)?;
+            let write = |out: &mut dyn Write| match sub_m.value_of("format") {
+                Some("follows") => follows.write_adjacency(out, contacts::Relation::Follow),
+                Some("blocks") => follows.write_adjacency(out, contacts::Relation::Block),
+                _ => follows.write_json(out),
+            };
+            if out_path == "-" {
+                write(&mut stdout().lock())
+            } else {
+                write(&mut output::create_file(out_path)?)
+            }
+        }
+        ("count", Some(sub_m)) => {
+            // Not opened with input::open_log: a broken log is counted up to
+            // where it breaks.
+            if !input::count(sub_m.value_of("in").unwrap(), sub_m.is_present("json"))? {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        ("diff", Some(sub_m)) => {
+            let a = input::open_log(sub_m.value_of("a").unwrap())?;
+            let b = input::open_log(sub_m.value_of("b").unwrap())?;
+            let format = report::Format::from_args(
+                sub_m.value_of("format") == Some("json"),
+                sub_m.value_of("template"),
+            )?;
+            diff::diff(&a, &b, &format)
+        }
+        ("doctor", Some(sub_m)) => {
+            let dir = match sub_m.value_of("DIR") {
+                Some(d) => PathBuf::from(d),
+                None => match dirs::home_dir() {
+                    Some(home) => home.join(".ssb"),
+                    None => {
+                        eprintln!("Can't find home directory; pass the ssb directory.");
+                        process::exit(1);
+                    }
+                },
+            };
+            doctor::doctor(&dir)
+        }
         ("extract", Some(sub_m)) => {
             let in_path = sub_m.value_of("in").unwrap();
             let out_path = sub_m.value_of("out").unwrap();
-            let feed_id = sub_m.value_of("id").unwrap();
+            let feed_id = id_arg(sub_m, "id");
+            let feed_id = feed_id.as_deref();
+            let filter_cmd = sub_m.value_of("filter_cmd");
             let overwrite = sub_m.is_present("overwrite");
+            let backup = sub_m.is_present("backup");
             let invert = sub_m.is_present("invert");
+            let channels: Vec<&str> = sub_m.values_of("channel").into_iter().flatten().collect();
+            let mentioned = id_arg(sub_m, "mentions");
+            let mentioned = mentioned.as_deref();
+            let keys: Option<HashSet<String>> = match sub_m.value_of("keys_file") {
+                Some(path) => Some(keyset::read(path)?.into_iter().collect()),
+                None => None,
+            };
+            let as_of = sub_m.value_of("as_of").map(|d| {
+                render::parse_date(d).unwrap_or_else(|| {
+                    eprintln!("Invalid --as-of date: {}", d);
+                    process::exit(1);
+                })
+            });
+            let received = sub_m.is_present("received");
+            // Feeds that have reached a message from after `as_of`.
+            let past: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+            let before = |v: &Value, as_of: f64| {
+                let author = match msg::author(v) {
+                    Some(a) => a,
+                    None => return false,
+                };
+                if past.borrow().contains(author) {
+                    return false;
+                }
+                let time = if received {
+                    msg::received_timestamp(v)
+                } else {
+                    msg::asserted_timestamp(v)
+                };
+                if time.is_some_and(|t| t < as_of) {
+                    return true;
+                }
+                past.borrow_mut().insert(author.to_string());
+                false
+            };
 
-            if !overwrite && Path::new(out_path).exists() {
-                eprintln!("Output path `{}` exists.", out_path);
-                eprintln!("Use `--overwrite` option to overwrite.");
+            if !output::prepare(out_path, &[in_path], overwrite, backup)? {
                 return Ok(());
             }
 
-            let in_log = OffsetLog::<u32>::open_read_only(in_path)?;
-            if in_log.end() == 0 {
-                eprintln!("Input offset log file is empty.");
+            let in_log = input::open_log(in_path)?;
+            if !output::check_space(out_path, in_log.end(), false)? {
                 return Ok(());
             }
 
-            let file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&out_path)?;
-
-            let out_log = OffsetLog::<u32>::from_file(file)?;
+            let out_log = output::create_log(out_path)?;
 
-            println!("Copying feed id: {}", feed_id);
+            if let Some(feed_id) = feed_id {
+                println!("Copying feed id: {}", feed_id);
+            }
             eprintln!(" from offset log at path:     {}", in_path);
             eprintln!(" into new offset log at path: {}", out_path);
 
-            if invert {
-                copy_log_entries_using_author(in_log, out_log, |id| id != feed_id)
+            // A feed asked for by id is copied even if it's on the skip list.
+            let skip = skip_list(&app_m)?;
+            let author_matches = |id: &str| {
+                feed_id.is_none_or(|f| (id == f) != invert)
+                    && (feed_id == Some(id) || !skip.contains(id))
+            };
+            let matches = |v: &Value| {
+                msg::author(v).is_some_and(author_matches)
+                    && as_of.is_none_or(|t| before(v, t))
+                    && (channels.is_empty() || channels.iter().any(|c| msg::in_channel(v, c)))
+                    && mentioned.is_none_or(|id| msg::mentions(v, id))
+                    && keys
+                        .as_ref()
+                        .is_none_or(|keys| msg::key(v).is_some_and(|k| keys.contains(k)))
+            };
+
+            match filter_cmd {
+                Some(cmd) => {
+                    let cmd = FilterCmd::spawn(cmd)?;
+                    filter_cmd::copy_filtered(in_log, out_log, matches, cmd)?
+                }
+                None if channels.is_empty()
+                    && mentioned.is_none()
+                    && keys.is_none()
+                    && as_of.is_none() =>
+                {
+                    copy_log_entries_using_author(in_log, out_log, author_matches)?
+                }
+                None => copy_log_entries(in_log, out_log, |e| {
+                    msg::parse(e).is_some_and(|v| matches(&v))
+                })?,
+            }
+
+            if !sub_m.is_present("validate") {
+                return Ok(());
+            }
+            let failure =
+                match validate::first_failure(&OffsetLog::<u32>::open_read_only(out_path)?)? {
+                    Some(f) => f,
+                    None => {
+                        println!("Output validates.");
+                        return Ok(());
+                    }
+                };
+            println!(
+                "Output doesn't validate from offset {} ({} #{}): {}",
+                failure.offset,
+                failure.author.as_deref().unwrap_or("unknown feed"),
+                failure.sequence.map_or("?".to_string(), |s| s.to_string()),
+                failure.why
+            );
+            if sub_m.is_present("trim_to_valid") {
+                validate::trim(out_path, failure.offset)?;
+                println!(
+                    "Trimmed the output to the {} entries before it ({}).",
+                    units::count(failure.valid),
+                    units::bytes(failure.offset)
+                );
             } else {
-                copy_log_entries_using_author(in_log, out_log, |id| id == feed_id)
+                println!(
+                    "The {} entries before it are valid; `--trim-to-valid` cuts the output there.",
+                    units::count(failure.valid)
+                );
+                process::exit(1);
+            }
+            Ok(())
+        }
+        ("repair", Some(sub_m)) => {
+            // The input is broken by definition, so it isn't opened with
+            // input::open_log.
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = match sub_m.value_of("out") {
+                Some(p) => p,
+                None => return repair::repair_in_place(in_path),
+            };
+            if !output::prepare(
+                out_path,
+                &[in_path],
+                sub_m.is_present("overwrite"),
+                sub_m.is_present("backup"),
+            )? {
+                return Ok(());
+            }
+            let in_len = std::fs::metadata(in_path)?.len();
+            if !output::check_space(out_path, in_len, false)? {
+                return Ok(());
+            }
+            let out_log = output::create_log(out_path)?;
+            repair::repair(in_path, out_log)
+        }
+        ("salvage", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+
+            if !output::prepare(
+                out_path,
+                &[in_path],
+                sub_m.is_present("overwrite"),
+                sub_m.is_present("backup"),
+            )? {
+                return Ok(());
+            }
+            // The input's framing can't be trusted, so it isn't opened as a log.
+            let in_len = std::fs::metadata(in_path)?.len();
+            if !output::check_space(out_path, in_len, false)? {
+                return Ok(());
             }
+            let out_log = output::create_log(out_path)?;
+            salvage::salvage(
+                in_path,
+                out_log,
+                sub_m.is_present("keep_unverified"),
+                sub_m.is_present("salvage_io"),
+            )
         }
         ("sort", Some(sub_m)) => {
             let in_path = sub_m.value_of("in").unwrap();
             let out_path = sub_m.value_of("out").unwrap();
             let overwrite = sub_m.is_present("overwrite");
+            let backup = sub_m.is_present("backup");
 
-            if !overwrite && Path::new(out_path).exists() {
-                eprintln!("Output path `{}` exists.", out_path);
-                eprintln!("Use `--overwrite` option to overwrite.");
+            if !output::prepare(out_path, &[in_path], overwrite, backup)? {
                 return Ok(());
             }
 
-            let file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&out_path)?;
-
-            let mut out_log = OffsetLog::<u32>::from_file(file)?;
-
-            let in_log = OffsetLog::<u32>::open_read_only(in_path)?;
-            if in_log.end() == 0 {
-                eprintln!("Input offset log file is empty.");
+            let in_log = input::open_log(in_path)?;
+            if !output::check_space(out_path, in_log.end(), true)? {
                 return Ok(());
             }
 
+            let mut out_log = output::create_log(out_path)?;
+
             eprintln!(" from offset log at path:     {}", in_path);
             eprintln!(" into new offset log at path: {}", out_path);
 
-            let mut entries = in_log
-                .iter()
-                .map(|entry| (get_entry_timestamp(&entry), entry.offset))
-                .collect::<Vec<_>>();
+            let offsets = if sub_m.is_present("shuffle") {
+                let offsets = shuffle::order(&in_log, parse_number(sub_m, "seed"))?;
+                eprintln!(
+                    " shuffled {} entries, writing out to new offset file",
+                    units::count(offsets.len() as u64)
+                );
+                offsets
+            } else {
+                let mut progress = Progress::new(in_log.end());
+                let mut entries = Vec::new();
+                for entry in in_log.iter() {
+                    entries.push((get_entry_timestamp(&entry), entry.offset));
+                    progress.update(entry.offset, || {
+                        format!("Read {} entries", units::count(entries.len() as u64))
+                    })?;
+                }
+                progress.finish(&format!(
+                    "Read {} entries",
+                    units::count(entries.len() as u64)
+                ))?;
 
-            entries.par_sort_unstable_by(|(a, _), (b, _)| a.partial_cmp(&b).unwrap());
+                entries.par_sort_unstable_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
 
-            eprintln!(
-                " sorted {} entries, writing out to new offset file",
-                entries.len()
-            );
+                eprintln!(
+                    " sorted {} entries, writing out to new offset file",
+                    units::count(entries.len() as u64)
+                );
+                entries
+                    .into_iter()
+                    .map(|(_, offset)| offset)
+                    .collect::<Vec<u64>>()
+            };
 
-            entries.iter().for_each(|(_, offset)| {
-                let entry = in_log.get(*offset).unwrap();
-                out_log.append(&entry).unwrap();
-            });
+            let mut progress = Progress::new(offsets.len() as u64);
+            for (i, offset) in offsets.iter().enumerate() {
+                let entry = in_log.get(*offset)?;
+                out_log.append(&entry)?;
+                progress.update(i as u64, || {
+                    format!("Wrote {} entries", units::count(i as u64))
+                })?;
+            }
+            progress.finish(&format!(
+                "Wrote {} entries",
+                units::count(offsets.len() as u64)
+            ))?;
+
+            Ok(())
+        }
+
+        ("export", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let value_only = sub_m.is_present("value");
+            let out_path = sub_m.value_of("out").unwrap();
+            if out_path != "-"
+                && !output::prepare(out_path, &[in_path], sub_m.is_present("overwrite"), false)?
+            {
+                return Ok(());
+            }
+            let log = input::open_log(in_path)?;
+            let state_path = sub_m.value_of("since_state");
+            // Held until the new state is written, so two runs can't export
+            // the same messages and then both move the state on.
+            let _lock = state_path.map(output::lock_replaced).transpose()?;
+            let from = match state_path {
+                Some(p) => {
+                    let state = export::read_state(p)?;
+                    if state.offset > log.end() || state.log_size > log.end() {
+                        eprintln!(
+                            "`{}` is {} long, shorter than when `{}` was last updated ({}): was it replaced?",
+                            in_path,
+                            units::bytes(log.end()),
+                            p,
+                            units::bytes(state.log_size)
+                        );
+                        eprintln!("Remove the state file to export the whole log again.");
+                        process::exit(1);
+                    }
+                    state.offset
+                }
+                None => 0,
+            };
+            let annotations = Annotations::load(in_path)?;
+            let end = if out_path == "-" {
+                export::export(&log, from, &annotations, stdout(), value_only)?
+            } else {
+                export::export(
+                    &log,
+                    from,
+                    &annotations,
+                    output::create_file(out_path)?,
+                    value_only,
+                )?
+            };
+            if let Some(p) = state_path {
+                export::write_state(
+                    p,
+                    &export::State {
+                        offset: end,
+                        log_size: log.end(),
+                    },
+                )?;
+            }
+            Ok(())
+        }
+        ("forks", Some(sub_m)) => {
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            if chains::forks(&log)? > 0 {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        ("frontier", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+            if out_path != "-"
+                && !output::prepare(out_path, &[in_path], sub_m.is_present("overwrite"), false)?
+            {
+                return Ok(());
+            }
+            let frontier = frontier::scan(&input::open_log(in_path)?)?;
+            if out_path == "-" {
+                println!("{}", serde_json::to_string_pretty(&frontier)?);
+                Ok(())
+            } else {
+                frontier::write(out_path, &frontier)
+            }
+        }
+        ("compare-frontier", Some(sub_m)) => {
+            let a = frontier::read(sub_m.value_of("a").unwrap())?;
+            let b = frontier::read(sub_m.value_of("b").unwrap())?;
+            frontier::compare(&a, &b);
+            Ok(())
+        }
+        ("fsck", Some(sub_m)) => {
+            // Not opened with input::open_log, which refuses broken logs.
+            if !input::fsck(sub_m.value_of("FILE").unwrap())? {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        ("gaps", Some(sub_m)) => {
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            chains::gaps(&log, sub_m.is_present("all"))
+        }
+        ("get", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let (key, feed) = (id_arg(sub_m, "key"), id_arg(sub_m, "feed"));
+            let lookup = match (key.as_deref(), feed.as_deref()) {
+                (Some(key), _) => peek::Lookup::Key(key),
+                (_, Some(feed)) => peek::Lookup::Sequence(feed, parse_number(sub_m, "seq")),
+                _ => peek::Lookup::Offset(parse_number(sub_m, "offset")),
+            };
+            let printer = peek::Printer {
+                annotations: &Annotations::load(in_path)?,
+                raw: !sub_m.is_present("rendered"),
+                json: sub_m.is_present("json"),
+            };
+            let log = input::open_log(in_path)?;
+            if !peek::get(in_path, &log, &lookup, &printer)? {
+                eprintln!("No such message.");
+                process::exit(1);
+            }
+            Ok(())
+        }
+        ("git-ssb", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let (repo, out_path) = match (sub_m.value_of("repo"), sub_m.value_of("out")) {
+                (Some(repo), Some(out_path)) => (repo, out_path),
+                _ => {
+                    let log = input::open_log(in_path)?;
+                    return git_ssb::summary(&log);
+                }
+            };
+            if !output::prepare(
+                out_path,
+                &[in_path],
+                sub_m.is_present("overwrite"),
+                sub_m.is_present("backup"),
+            )? {
+                return Ok(());
+            }
+            let in_log = input::open_log(in_path)?;
+            if !output::check_space(out_path, in_log.end(), false)? {
+                return Ok(());
+            }
+            let out_log = output::create_log(out_path)?;
+            git_ssb::extract(in_log, out_log, repo)
+        }
+        ("grep", Some(sub_m)) => {
+            let target =
+                grep::Target::new(sub_m.value_of("pattern").unwrap(), sub_m.value_of("field"))
+                    .unwrap_or_else(|e| {
+                        eprintln!("Invalid --pattern: {}", e);
+                        process::exit(1);
+                    });
+            let preview = if sub_m.is_present("preview")
+                || sub_m.is_present("preview_len")
+                || sub_m.is_present("preview_field")
+            {
+                let fields: Vec<&str> = sub_m
+                    .values_of("preview_field")
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                let len = match sub_m.value_of("preview_len") {
+                    Some(_) => parse_number(sub_m, "preview_len"),
+                    None => 80,
+                };
+                Some(grep::Preview::new(&fields, len))
+            } else {
+                None
+            };
+            let in_path = sub_m.value_of("in").unwrap();
+            let log = input::open_log(in_path)?;
+            grep::grep(
+                &log,
+                &Annotations::load(in_path)?,
+                &target,
+                preview.as_ref(),
+            )
+        }
+        ("head", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let log = input::open_log(in_path)?;
+            let printer = peek::Printer {
+                annotations: &Annotations::load(in_path)?,
+                raw: sub_m.is_present("raw"),
+                json: sub_m.is_present("json"),
+            };
+            peek::head(&log, parse_number(sub_m, "lines"), &printer)
+        }
+        ("interactions", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let top = parse_number(sub_m, "top");
+            let log = input::open_log(in_path)?;
+            let format =
+                report::Format::from_args(sub_m.is_present("json"), sub_m.value_of("template"))?;
+            interactions::interactions(log, top, &format)
+        }
+        ("key-index", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+            let overwrite = sub_m.is_present("overwrite");
+
+            if out_path == "-" {
+                let log = input::open_log(in_path)?;
+                return key_index::write_key_index(log, stdout());
+            }
+            if !output::prepare(out_path, &[in_path], overwrite, false)? {
+                return Ok(());
+            }
+            let log = input::open_log(in_path)?;
+            key_index::write_key_index(log, output::create_file(out_path)?)
+        }
+        ("keys", Some(keys_m)) => {
+            let (missing, sub_m) = match keys_m.subcommand() {
+                ("present", Some(sub_m)) => (false, sub_m),
+                ("missing", Some(sub_m)) => (true, sub_m),
+                _ => unreachable!(),
+            };
+            let keys = keyset::read(sub_m.value_of("keys_file").unwrap())?;
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            keyset::filter(&log, &keys, missing)
+        }
+        ("lint", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let config = config::load(app_m.value_of("config"))?;
+            let log = input::open_log(in_path)?;
+            lint::lint_log(log, &config.lint_rules, sub_m.is_present("show"))
+        }
+        ("monitor", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let expected = match sub_m.value_of("expect") {
+                Some(p) => frontier::read(p)?,
+                None => frontier::Frontier::new(),
+            };
+            let alarms = monitor::Alarms {
+                silence: sub_m
+                    .value_of("alert_on_silence")
+                    .map(|s| parse_duration(s, "alert-on-silence")),
+                rate: sub_m.value_of("alert_on_rate").map(parse_rate),
+                cmd: sub_m.value_of("alert_cmd").map(String::from),
+            };
+            let interval = if sub_m.is_present("interval") {
+                Some(Duration::from_secs(parse_number(sub_m, "interval")))
+            } else if alarms.silence.is_some() || alarms.rate.is_some() {
+                Some(Duration::from_secs(10))
+            } else {
+                None
+            };
+            monitor::monitor(in_path, &expected, interval, &alarms)
+        }
+        ("stats", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let log = input::open_log(in_path)?;
+            let skip = skip_list(&app_m)?;
+            let mut analyzers = if sub_m.is_present("apps") {
+                apps::registry()
+            } else {
+                Vec::new()
+            };
+            if let Some(b) = sub_m.value_of("timeline") {
+                let bucket = match b {
+                    "week" => timeline::Bucket::Week,
+                    "month" => timeline::Bucket::Month,
+                    "quarter" => timeline::Bucket::Quarter,
+                    d => timeline::Bucket::Every(parse_duration(d, "timeline")),
+                };
+                let tz = sub_m.value_of("tz").unwrap_or("UTC");
+                let offset = timeline::parse_offset(tz).unwrap_or_else(|| {
+                    eprintln!(
+                        "Invalid value `{}` for --tz: expected UTC or an offset like +02:00.",
+                        tz
+                    );
+                    process::exit(1);
+                });
+                return timeline::timeline_report(
+                    &log,
+                    &bucket,
+                    offset,
+                    sub_m.is_present("received"),
+                    &skip,
+                );
+            }
+            let scan = stats::scan_feeds(&log, &mut analyzers, &skip)?;
+            if let Some(other_path) = sub_m.value_of("compare") {
+                let other = input::open_log(other_path)?;
+                let other_scan = stats::scan_feeds(&other, &mut [], &skip)?;
+                return stats::compare_report(
+                    &scan.feeds,
+                    &other_scan.feeds,
+                    parse_number(sub_m, "top"),
+                );
+            }
+            let names = match sub_m.is_present("resolve_names") {
+                true => Some(names::Names::scan(&log)?),
+                false => None,
+            };
+            let format =
+                report::Format::from_args(sub_m.is_present("json"), sub_m.value_of("template"))?;
+            if !matches!(format, report::Format::Text) {
+                return stats::emit_report(
+                    &scan,
+                    parse_number(sub_m, "top"),
+                    names.as_ref(),
+                    sub_m.is_present("received"),
+                    sub_m.is_present("list"),
+                    &format,
+                );
+            }
+            stats::summary_report(&scan, parse_number(sub_m, "top"), names.as_ref())?;
+            stats::activity_report(
+                &scan.feeds,
+                sub_m.is_present("received"),
+                sub_m.is_present("list"),
+            )?;
+            stats::apps_report(&analyzers);
+            Ok(())
+        }
+        ("sync", Some(sub_m)) => {
+            let from_path = sub_m.value_of("from").unwrap();
+            let into_path = sub_m.value_of("into").unwrap();
+            if let Some(reason) = output::in_use(Path::new(into_path))? {
+                eprintln!("`{}` is {}.", into_path, reason);
+                eprintln!("Stop sbot before appending to its log.");
+                return Ok(());
+            }
+            let from = input::open_log(from_path)?;
+            // Checked like any input, then reopened for appending.
+            drop(input::open_log(into_path)?);
+            let _lock = output::lock(into_path)?;
+            let mut into = OffsetLog::<u32>::new(into_path)?;
+            sync::sync(&from, &mut into, sub_m.is_present("dry_run"))
+        }
+        ("tail", Some(sub_m)) => {
+            // Not opened with input::open_log, which would refuse a live log
+            // with a half-written entry at the end.
+            let in_path = sub_m.value_of("in").unwrap();
+            let printer = peek::Printer {
+                annotations: &Annotations::load(in_path)?,
+                raw: sub_m.is_present("raw"),
+                json: sub_m.is_present("json"),
+            };
+            peek::tail(
+                in_path,
+                parse_number(sub_m, "lines"),
+                sub_m.is_present("follow"),
+                &printer,
+            )
+        }
+        ("slice", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+            let bound = |name, default| {
+                if sub_m.is_present(name) {
+                    parse_number(sub_m, name)
+                } else {
+                    default
+                }
+            };
+            let offsets = bound("from_offset", 0)..bound("to_offset", u64::MAX);
+            let entries = bound("from_seq", 0)..bound("to_seq", u64::MAX);
+            if offsets.is_empty() || entries.is_empty() {
+                eprintln!("The range to copy is empty: each --from-* must be below its --to-*.");
+                process::exit(1);
+            }
 
+            if !output::prepare(
+                out_path,
+                &[in_path],
+                sub_m.is_present("overwrite"),
+                sub_m.is_present("backup"),
+            )? {
+                return Ok(());
+            }
+            let in_log = input::open_log(in_path)?;
+            let end = in_log.end().min(offsets.end);
+            if !output::check_space(out_path, end.saturating_sub(offsets.start), false)? {
+                return Ok(());
+            }
+            let mut out = FrameWriter::new(output::create_file(out_path)?);
+            let copied = copy_frames(in_path, &mut out, end, |i, f| {
+                if f.offset >= offsets.end || i >= entries.end {
+                    None
+                } else {
+                    Some(offsets.contains(&f.offset) && entries.contains(&i))
+                }
+            })?;
+            println!(
+                "Copied {} entries ({}).",
+                units::count(copied),
+                units::bytes(out.finish()?)
+            );
+            Ok(())
+        }
+        ("truncate", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            let out_path = sub_m.value_of("out").unwrap();
+            let (at_offset, count) = if sub_m.is_present("at_offset") {
+                (parse_number(sub_m, "at_offset"), u64::MAX)
+            } else {
+                (u64::MAX, parse_number(sub_m, "count"))
+            };
+
+            if !output::prepare(
+                out_path,
+                &[in_path],
+                sub_m.is_present("overwrite"),
+                sub_m.is_present("backup"),
+            )? {
+                return Ok(());
+            }
+            let in_log = input::open_log(in_path)?;
+            if !output::check_space(out_path, in_log.end().min(at_offset), false)? {
+                return Ok(());
+            }
+            let end = in_log.end().min(at_offset);
+            let mut out = FrameWriter::new(output::create_file(out_path)?);
+            let copied = copy_frames(in_path, &mut out, end, |i, f| {
+                if f.offset < at_offset && i < count {
+                    Some(true)
+                } else {
+                    None
+                }
+            })?;
+            println!(
+                "Copied {} entries ({}).",
+                units::count(copied),
+                units::bytes(out.finish()?)
+            );
             Ok(())
         }
+        ("types", Some(sub_m)) => {
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            let scan = stats::scan_feeds(&log, &mut [], &skip_list(&app_m)?)?;
+            let format =
+                report::Format::from_args(sub_m.is_present("json"), sub_m.value_of("template"))?;
+            stats::types_report(&scan, sub_m.value_of("sort") == Some("count"), &format)
+        }
+        ("urls", Some(sub_m)) => {
+            let in_path = sub_m.value_of("in").unwrap();
+            match sub_m.value_of("csv") {
+                Some("-") => {
+                    let log = input::open_log(in_path)?;
+                    urls::write_csv(&urls::scan(&log)?, stdout())
+                }
+                Some(out_path) => {
+                    if !output::prepare(out_path, &[in_path], sub_m.is_present("overwrite"), false)?
+                    {
+                        return Ok(());
+                    }
+                    let log = input::open_log(in_path)?;
+                    urls::write_csv(&urls::scan(&log)?, output::create_file(out_path)?)
+                }
+                None => {
+                    let log = input::open_log(in_path)?;
+                    urls::report(&urls::scan(&log)?, parse_number(sub_m, "top"));
+                    Ok(())
+                }
+            }
+        }
+        ("vault", Some(vault_m)) => match vault_m.subcommand() {
+            ("add", Some(sub_m)) => {
+                let mut vault = vault::Vault::open(Path::new(sub_m.value_of("dir").unwrap()))?;
+                for in_path in sub_m.values_of("LOG").unwrap() {
+                    eprintln!("Adding {}", in_path);
+                    let in_log = input::open_log(in_path)?;
+                    vault.add(&in_log)?;
+                }
+                Ok(())
+            }
+            ("gc", Some(sub_m)) => {
+                const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+                let vault = vault::Vault::open(Path::new(sub_m.value_of("dir").unwrap()))?;
+                let mut rules = vault::Retention {
+                    keep_feeds: HashSet::new(),
+                    max_age: None,
+                    vote_max_age: None,
+                };
+                if sub_m.is_present("keep_years") {
+                    rules.max_age = Some(parse_number::<f64>(sub_m, "keep_years") * 365.0 * DAY_MS);
+                }
+                if sub_m.is_present("drop_votes_older_than") {
+                    rules.vote_max_age =
+                        Some(parse_number::<f64>(sub_m, "drop_votes_older_than") * DAY_MS);
+                }
+                if rules.max_age.is_none() && rules.vote_max_age.is_none() {
+                    eprintln!("Nothing to drop: pass --keep-years and/or --drop-votes-older-than.");
+                    process::exit(1);
+                }
+                if let Some(ids) = sub_m.values_of("keep_followed_by") {
+                    let follows = contacts::Follows::scan(vault.log())?;
+                    for id in ids {
+                        rules
+                            .keep_feeds
+                            .extend(follows.hops(&parse_id(id), 1).into_keys());
+                    }
+                }
+                vault.gc(&rules, sub_m.is_present("dry_run"))
+            }
+            ("materialize", Some(sub_m)) => {
+                let dir = sub_m.value_of("dir").unwrap();
+                let out_path = sub_m.value_of("out").unwrap();
+
+                if !Path::new(dir).join("messages.offset").exists() {
+                    eprintln!("No vault at `{}`.", dir);
+                    process::exit(1);
+                }
+                let vault_log = Path::new(dir).join("messages.offset");
+                let vault_log = vault_log.to_string_lossy();
+                if !output::prepare(
+                    out_path,
+                    &[&vault_log],
+                    sub_m.is_present("overwrite"),
+                    sub_m.is_present("backup"),
+                )? {
+                    return Ok(());
+                }
+                let vault = vault::Vault::open(Path::new(dir))?;
+                if !output::check_space(out_path, vault.size(), false)? {
+                    return Ok(());
+                }
+
+                let values = |name| -> Vec<String> {
+                    sub_m
+                        .values_of(name)
+                        .into_iter()
+                        .flatten()
+                        .map(String::from)
+                        .collect()
+                };
+                let mut feeds: Option<HashSet<String>> = None;
+                if sub_m.is_present("feed") {
+                    feeds = Some(values("feed").iter().map(|f| parse_id(f)).collect());
+                }
+                // `--hops` alone starts from this install's own feed, as when
+                // seeding a new sbot for the same identity.
+                let root = match id_arg(sub_m, "root") {
+                    None if sub_m.is_present("hops") => {
+                        let ssb_dir = dirs::home_dir().unwrap_or_default().join(".ssb");
+                        match wizard::own_id(&ssb_dir) {
+                            Some(id) => Some(id),
+                            None => {
+                                eprintln!(
+                                    "Can't read a feed id from `{}`; pass --root.",
+                                    ssb_dir.join("secret").display()
+                                );
+                                process::exit(1);
+                            }
+                        }
+                    }
+                    root => root,
+                };
+                if let Some(root) = root {
+                    let follows = contacts::Follows::scan(vault.log())?;
+                    let hops = match sub_m.is_present("hops") {
+                        true => parse_number(sub_m, "hops"),
+                        false => 2,
+                    };
+                    feeds
+                        .get_or_insert_with(HashSet::new)
+                        .extend(follows.hops(&root, hops).into_keys());
+                }
+                let mut selection = vault::Selection {
+                    feeds,
+                    types: values("type"),
+                    max_age: None,
+                    priority_types: values("priority_type"),
+                };
+                if sub_m.is_present("since_days") {
+                    selection.max_age = Some(
+                        parse_number::<f64>(sub_m, "since_days") * 24.0 * 60.0 * 60.0 * 1000.0,
+                    );
+                }
 
+                let out_log = output::create_log(out_path)?;
+                vault.materialize(out_log, &selection)
+            }
+            _ => unreachable!(),
+        },
+        ("verify", Some(sub_m)) => {
+            let log = input::open_log(sub_m.value_of("in").unwrap())?;
+            let skip = skip_list(&app_m)?;
+            if sub_m.is_present("partial") {
+                return chains::partial(&log, &skip);
+            }
+            if chains::verify(&log, &skip)?.broken > 0 {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        ("verify-checksums", Some(sub_m)) => {
+            // Not opened with input::open_log: a damaged log is what this is
+            // looking for.
+            let in_path = sub_m.value_of("in").unwrap();
+            if !checksum::verify(in_path, sub_m.value_of("sums").unwrap())? {
+                process::exit(1);
+            }
+            Ok(())
+        }
         ("view", Some(sub_m)) => {
             let path = sub_m.value_of("FILE").unwrap();
 
-            let log = OffsetLog::<u32>::open_read_only(path)?;
-            view_log(log)
+            let log = input::open_log(path)?;
+            let scope = match (
+                id_arg(sub_m, "threads_feed"),
+                sub_m.value_of("threads_channel"),
+            ) {
+                (Some(feed), _) => Some(threads::Scope::Feed(feed)),
+                (_, Some(channel)) => Some(threads::Scope::Channel(channel.to_string())),
+                _ => None,
+            };
+            match scope {
+                Some(scope) => {
+                    let threads = threads::collect(&log, &scope)?;
+                    threads::view(&threads, sub_m.is_present("markdown"))
+                }
+                None => view_log(
+                    log,
+                    &mut Annotations::load(path)?,
+                    sub_m.is_present("raw"),
+                    sub_m.is_present("markdown"),
+                ),
+            }
         }
+        ("wizard", Some(_)) => wizard::wizard(),
         _ => {
             println!("{}", app_m.usage());
             Ok(())
@@ -194,6 +3015,90 @@ fn main() -> Result<(), Error> {
     }
 }
 
+// Parse a numeric option, exiting with a message if it isn't one.
+fn parse_number<T: std::str::FromStr>(m: &ArgMatches, name: &str) -> T {
+    let s = m.value_of(name).unwrap();
+    s.parse().unwrap_or_else(|_| {
+        eprintln!(
+            "Invalid value `{}` for --{}: expected a number.",
+            s,
+            name.replace('_', "-")
+        );
+        process::exit(1);
+    })
+}
+
+// A feed id or message key, which may also be given as an SSB URI, exiting
+// with a message if it's a URI of something else.
+fn parse_id(s: &str) -> String {
+    match uri::id(s) {
+        Some(id) => id.into_owned(),
+        None => {
+            eprintln!("Invalid id `{}`: not the SSB URI of a feed or message.", s);
+            process::exit(1);
+        }
+    }
+}
+
+fn id_arg(m: &ArgMatches, name: &str) -> Option<String> {
+    m.value_of(name).map(parse_id)
+}
+
+// The feeds on the config's skip list, or none with `--no-skip-list`.
+fn skip_list(app_m: &ArgMatches) -> Result<HashSet<String>, Error> {
+    if app_m.is_present("no_skip_list") {
+        return Ok(HashSet::new());
+    }
+    config::load(app_m.value_of("config"))?.skip_list()
+}
+
+// Parse a duration like `90s`, `30m`, `1h` or `2d` (plain numbers are
+// seconds), exiting with a message if it isn't one.
+fn parse_duration(s: &str, name: &str) -> Duration {
+    let (n, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let secs = match unit {
+        "" | "s" | "sec" => Some(1),
+        "m" | "min" => Some(60),
+        "h" | "hour" => Some(60 * 60),
+        "d" | "day" => Some(24 * 60 * 60),
+        _ => None,
+    };
+    match (n.parse::<u64>(), secs) {
+        (Ok(n), Some(secs)) => Duration::from_secs(n * secs),
+        _ => {
+            eprintln!(
+                "Invalid value `{}` for --{}: expected a duration like 90s, 30m, 1h or 2d.",
+                s, name
+            );
+            process::exit(1);
+        }
+    }
+}
+
+// Parse a rate like `1000/min` into a count and the duration it's per.
+fn parse_rate(s: &str) -> (u64, Duration) {
+    let mut parts = s.splitn(2, '/');
+    let count = parts.next().and_then(|n| n.parse().ok());
+    match (count, parts.next()) {
+        (Some(count), Some(per)) => {
+            // `1000/min` means per one minute
+            let per = if per.starts_with(|c: char| c.is_ascii_digit()) {
+                per.to_string()
+            } else {
+                format!("1{}", per)
+            };
+            (count, parse_duration(&per, "alert-on-rate"))
+        }
+        _ => {
+            eprintln!(
+                "Invalid value `{}` for --alert-on-rate: expected a rate like 1000/min.",
+                s
+            );
+            process::exit(1);
+        }
+    }
+}
+
 // copy if author id matches predicate
 fn copy_log_entries_using_author<F>(
     in_log: OffsetLog<u32>,
@@ -211,12 +3116,39 @@ where
                 .get("value")
                 .and_then(|v| v.get("author"))
                 .and_then(|v| v.as_str())
-                .map_or(false, |v| should_write(v)),
+                .is_some_and(&should_write),
             Err(_) => false,
         }
     })
 }
 
+// Copy the frames of the log at `in_path` that `keep` picks, given each
+// frame's index, without parsing them. Stops at the first frame `keep`
+// returns None for. Returns how many frames were copied.
+fn copy_frames<F>(in_path: &str, out: &mut FrameWriter, end: u64, mut keep: F) -> Result<u64, Error>
+where
+    F: FnMut(u64, &RawFrame) -> Option<bool>,
+{
+    let mut progress = Progress::new(end);
+    let mut copied: u64 = 0;
+    for (i, f) in frame::frames(in_path)?.enumerate() {
+        let f = f?;
+        match keep(i as u64, &f) {
+            Some(true) => {
+                out.append(&f.data)?;
+                copied += 1;
+            }
+            Some(false) => {}
+            None => break,
+        }
+        progress.update(f.offset, || {
+            format!("Copied {} entries", units::count(copied))
+        })?;
+    }
+    progress.finish(&format!("Copied {} entries", units::count(copied)))?;
+    Ok(copied)
+}
+
 fn copy_log_entries<F>(
     in_log: OffsetLog<u32>,
     mut out_log: OffsetLog<u32>,
@@ -225,48 +3157,39 @@ fn copy_log_entries<F>(
 where
     F: Fn(&LogEntry) -> bool,
 {
-    let stdout = io::stdout();
-    let mut handle = stdout.lock();
-
-    let in_len = in_log.end();
-    if in_len == 0 {
-        eprintln!("Input offset log file is empty.");
-        return Ok(());
-    }
-
-    let iter = in_log.iter().map(|e| {
-        let sw = should_write(&e);
-        (e, sw)
-    });
+    let mut progress = Progress::new(in_log.end());
 
     let mut count: usize = 0;
-    let mut prev_pct: usize = 0;
-    let mut bytes: u64 = 0;
 
-    for (e, should_write) in iter {
-        let pct = (100.0 * (e.offset as f64 / in_len as f64)) as usize;
-
-        if should_write {
-            bytes = out_log.append(&e.data)?;
+    for e in in_log.iter() {
+        if should_write(&e) {
+            out_log.append(&e.data)?;
             count += 1;
         }
 
-        if should_write || (pct > prev_pct) {
-            write!(
-                handle,
-                "\rProgress: {}%\tCopied {} messages ({} bytes)",
-                pct, count, bytes
-            )?;
-            handle.flush()?;
-            prev_pct = pct;
-        }
+        progress.update(e.offset, || {
+            format!(
+                "Copied {} messages ({})",
+                units::count(count as u64),
+                units::bytes(out_log.end())
+            )
+        })?;
     }
-    println!("");
+    progress.finish(&format!(
+        "Copied {} messages ({})",
+        units::count(count as u64),
+        units::bytes(out_log.end())
+    ))?;
     println!("Done!");
     Ok(())
 }
 
-fn view_log(log: OffsetLog<u32>) -> Result<(), Error> {
+fn view_log(
+    log: OffsetLog<u32>,
+    annotations: &mut Annotations,
+    raw: bool,
+    markdown: bool,
+) -> Result<(), Error> {
     let stdin = stdin();
     let mut stdout = stdout().into_raw_mode()?;
 
@@ -274,24 +3197,71 @@ fn view_log(log: OffsetLog<u32>) -> Result<(), Error> {
         let v = serde_json::from_slice(&e.data).unwrap();
         (e, v)
     });
+    // The entry on screen, to redraw after annotating it.
+    let mut current: Option<(u64, Value)> = None;
 
-    iter.next()
-        .map(|(e, v)| print_entry(e.offset, &v, &mut stdout));
+    if let Some((e, v)) = iter.next() {
+        print_entry(e.offset, &v, annotations, raw, markdown, &mut stdout);
+        current = Some((e.offset, v));
+    }
 
-    for c in stdin.keys() {
+    let mut keys = stdin.keys();
+    while let Some(c) = keys.next() {
         match c? {
             Key::Char('q') | Key::Ctrl('c') | Key::Esc => {
                 break;
             }
-            Key::Up | Key::Left | Key::Char('p') | Key::Char('k') => {
-                iter.prev()
-                    .map(|(e, v)| print_entry(e.offset, &v, &mut stdout))
-                    .or_else(|| write!(stdout, "No record").ok());
-            }
-            Key::Down | Key::Right | Key::Char('n') | Key::Char('j') => {
-                iter.next()
-                    .map(|(e, v)| print_entry(e.offset, &v, &mut stdout))
-                    .or_else(|| write!(stdout, "No record").ok());
+            Key::Up | Key::Left | Key::Char('p') | Key::Char('k') => match iter.prev() {
+                Some((e, v)) => {
+                    print_entry(e.offset, &v, annotations, raw, markdown, &mut stdout);
+                    current = Some((e.offset, v));
+                }
+                None => write!(stdout, "No record")?,
+            },
+            Key::Down | Key::Right | Key::Char('n') | Key::Char('j') => match iter.next() {
+                Some((e, v)) => {
+                    print_entry(e.offset, &v, annotations, raw, markdown, &mut stdout);
+                    current = Some((e.offset, v));
+                }
+                None => write!(stdout, "No record")?,
+            },
+            Key::Char(';') => {
+                let (offset, v) = match &current {
+                    Some((o, v)) => (*o, v),
+                    None => continue,
+                };
+                let key = match msg::key(v) {
+                    Some(k) => k,
+                    None => continue,
+                };
+                write!(stdout, "\n\n\rNote (enter saves, esc cancels): ")?;
+                stdout.flush()?;
+                let mut note = String::new();
+                let mut save = false;
+                for c in keys.by_ref() {
+                    match c? {
+                        Key::Char('\n') => {
+                            save = true;
+                            break;
+                        }
+                        Key::Esc | Key::Ctrl('c') => break,
+                        Key::Backspace if !note.is_empty() => {
+                            note.pop();
+                            write!(stdout, "\x08 \x08")?;
+                        }
+                        Key::Char(c) => {
+                            note.push(c);
+                            write!(stdout, "{}", c)?;
+                        }
+                        _ => {}
+                    }
+                    stdout.flush()?;
+                }
+                if save && !note.trim().is_empty() {
+                    annotations.add(key, &[], Some(note.trim()));
+                    annotations.save()?;
+                }
+                print_entry(offset, v, annotations, raw, markdown, &mut stdout);
             }
             Key::Char(c) => {
                 eprintln!("KEY: {}", c);
@@ -316,17 +3286,33 @@ fn get_entry_timestamp(e: &LogEntry) -> f64 {
     }
 }
 
-fn print_entry<W: Write>(offset: u64, data: &serde_json::Value, mut stdout: &mut W) {
+fn print_entry<W: Write>(
+    offset: u64,
+    data: &serde_json::Value,
+    annotations: &Annotations,
+    raw: bool,
+    markdown: bool,
+    mut stdout: &mut W,
+) {
     write!(
         stdout,
-        "{}{}Press `j` or `k` to show the next or previous entry. Press `q` to exit.{}Offset: {}",
+        "{}{}Press `j` or `k` to show the next or previous entry, `;` to annotate it. Press `q` to exit.{}Offset: {}",
         termion::clear::All,
         termion::cursor::Goto(1, 1),
         termion::cursor::Goto(1, 2),
         offset
     )
     .unwrap();
-    print_lines(&to_string_pretty(&data).unwrap(), &mut stdout).unwrap();
+    if let Some(a) = msg::key(data).and_then(|k| annotations.get(k)) {
+        write!(stdout, "\n\rAnnotated: {}", a.summary()).unwrap();
+    }
+    let text = if raw {
+        to_string_pretty(&data).unwrap()
+    } else {
+        let width = termion::terminal_size().map_or(80, |(w, _)| w as usize);
+        render::render(data, width, markdown)
+    };
+    print_lines(&text, &mut stdout).unwrap();
     stdout.flush().unwrap();
 }
 
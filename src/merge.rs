@@ -0,0 +1,63 @@
+use std::collections::{BTreeMap, HashSet};
+
+use flumedb::flume_log::{Error, FlumeLog};
+use flumedb::offset_log::OffsetLog;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+pub struct Copied {
+    pub messages: u64,
+    // author -> messages skipped because their key was already copied
+    pub duplicates: BTreeMap<String, u64>,
+}
+
+impl Copied {
+    pub fn total_duplicates(&self) -> u64 {
+        self.duplicates.values().sum()
+    }
+}
+
+// Append the entries of `logs`, in order, to `out_log`, skipping every
+// message whose key has already been copied. Entries that aren't messages are
+// copied as they are. `name` labels the logs in progress output (eg. "Log").
+pub fn copy_unique(
+    logs: &[OffsetLog<u32>],
+    out_log: &mut OffsetLog<u32>,
+    name: &str,
+) -> Result<Copied, Error> {
+    let mut keys = HashSet::new();
+    let mut copied = Copied {
+        messages: 0,
+        duplicates: BTreeMap::new(),
+    };
+
+    for (i, log) in logs.iter().enumerate() {
+        let mut progress = Progress::new(log.end());
+        let status = |copied: &Copied| {
+            format!(
+                "{} {}: copied {} messages",
+                name,
+                i + 1,
+                units::count(copied.messages)
+            )
+        };
+        for e in log.iter() {
+            progress.update(e.offset, || status(&copied))?;
+            if let Some(v) = msg::parse(&e) {
+                if let Some(key) = msg::key(&v) {
+                    if !keys.insert(key.to_string()) {
+                        let author = msg::author(&v).unwrap_or("<unknown author>");
+                        *copied.duplicates.entry(author.to_string()).or_default() += 1;
+                        continue;
+                    }
+                }
+            }
+            out_log.append(&e.data)?;
+            copied.messages += 1;
+        }
+        progress.finish(&status(&copied))?;
+    }
+    Ok(copied)
+}
@@ -0,0 +1,169 @@
+use std::process::{self, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flumedb::flume_log::Error;
+
+use crate::frame::{self, FrameError};
+use crate::frontier::{self, Frontier};
+use crate::units;
+
+// Follows a (possibly live) log, keeping its frontier up to date.
+struct Watched<'a> {
+    path: &'a str,
+    offset: u64,
+    frontier: Frontier,
+    // frames read so far
+    appended: u64,
+}
+
+impl<'a> Watched<'a> {
+    // Read whatever has been appended since the last call. A frame that's
+    // still being written is left for next time.
+    fn catch_up(&mut self) -> Result<(), Error> {
+        for f in frame::frames_from(self.path, self.offset)? {
+            match f {
+                Ok(f) => {
+                    self.offset = f.next_offset();
+                    self.appended += 1;
+                    if let Ok(v) = serde_json::from_slice(&f.data) {
+                        frontier::update(&mut self.frontier, &v);
+                    }
+                }
+                Err(FrameError::Truncated { .. }) => break,
+                Err(FrameError::Io(e)) => return Err(e.into()),
+                Err(e) => {
+                    eprintln!("Stopped reading `{}`: {}", self.path, e);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Alarms on the log's growth as a whole, checked at every interval.
+pub struct Alarms {
+    // Alert when nothing has been appended for this long.
+    pub silence: Option<Duration>,
+    // Alert when more than this many messages are appended per duration.
+    pub rate: Option<(u64, Duration)>,
+    // Shell command run for every alert, with the alert in $FEEDRICK_ALERT.
+    pub cmd: Option<String>,
+}
+
+impl Alarms {
+    fn alert(&self, msg: String) {
+        println!("ALERT {}", msg);
+        if let Some(cmd) = &self.cmd {
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(cmd)
+                .env("FEEDRICK_ALERT", &msg)
+                .status();
+            match status {
+                Ok(s) if s.success() => {}
+                Ok(s) => eprintln!("Alert command exited with {}", s),
+                Err(e) => eprintln!("Couldn't run alert command: {}", e),
+            }
+        }
+    }
+}
+
+// Compare the log's frontier against `expected` and alert for every feed
+// that's behind. Without an interval, checks once and exits non-zero if any
+// feed is behind. With one, keeps checking and alerts when a feed that's
+// behind hasn't advanced since the previous check, or when the log's growth
+// trips one of `alarms`.
+pub fn monitor(
+    path: &str,
+    expected: &Frontier,
+    interval: Option<Duration>,
+    alarms: &Alarms,
+) -> Result<(), Error> {
+    let mut log = Watched {
+        path,
+        offset: 0,
+        frontier: Frontier::new(),
+        appended: 0,
+    };
+    log.catch_up()?;
+
+    let behind = report_behind(&log.frontier, expected, None, alarms);
+    let interval = match interval {
+        Some(i) => i,
+        None => {
+            if behind > 0 {
+                process::exit(1);
+            }
+            return Ok(());
+        }
+    };
+
+    let mut last_growth = Instant::now();
+    let mut silent = false;
+    loop {
+        let previous = log.frontier.clone();
+        let previous_appended = log.appended;
+        let started = Instant::now();
+        thread::sleep(interval);
+        log.catch_up()?;
+        report_behind(&log.frontier, expected, Some(&previous), alarms);
+
+        let appended = log.appended - previous_appended;
+        if appended > 0 {
+            last_growth = Instant::now();
+            silent = false;
+        }
+        if let Some(silence) = alarms.silence {
+            if !silent && last_growth.elapsed() >= silence {
+                alarms.alert(format!(
+                    "{} has not grown for {}s",
+                    path,
+                    last_growth.elapsed().as_secs()
+                ));
+                silent = true;
+            }
+        }
+        if let Some((limit, per)) = alarms.rate {
+            let rate = appended as f64 * per.as_secs_f64() / started.elapsed().as_secs_f64();
+            if rate > limit as f64 {
+                alarms.alert(format!(
+                    "{} grew by {} messages in {}s ({:.0} per {}s, limit {})",
+                    path,
+                    units::count(appended),
+                    started.elapsed().as_secs(),
+                    rate,
+                    per.as_secs(),
+                    units::count(limit)
+                ));
+            }
+        }
+    }
+}
+
+// Returns the number of alerts raised.
+fn report_behind(
+    current: &Frontier,
+    expected: &Frontier,
+    previous: Option<&Frontier>,
+    alarms: &Alarms,
+) -> usize {
+    let mut alerts = 0;
+    for (feed, &want) in expected {
+        let have = current.get(feed).copied().unwrap_or(0);
+        if have >= want {
+            continue;
+        }
+        match previous {
+            None => alarms.alert(format!("{} is at {}, expected {}", feed, have, want)),
+            Some(prev) if prev.get(feed).copied().unwrap_or(0) == have => alarms.alert(format!(
+                "{} stopped advancing at {}, expected {}",
+                feed, have, want
+            )),
+            Some(_) => continue,
+        }
+        alerts += 1;
+    }
+    alerts
+}
@@ -0,0 +1,129 @@
+use flumedb::log_entry::LogEntry;
+use serde_json::Value;
+
+// Accessors for the flume envelope around an ssb message:
+//   { "key": "%...", "value": { "author", "sequence", "timestamp", "content", ... }, "timestamp": <received> }
+
+pub fn parse(e: &LogEntry) -> Option<Value> {
+    serde_json::from_slice(&e.data).ok()
+}
+
+pub fn key(v: &Value) -> Option<&str> {
+    v.get("key").and_then(|k| k.as_str())
+}
+
+pub fn author(v: &Value) -> Option<&str> {
+    v.get("value")
+        .and_then(|v| v.get("author"))
+        .and_then(|a| a.as_str())
+}
+
+pub fn sequence(v: &Value) -> Option<u64> {
+    v.get("value")
+        .and_then(|v| v.get("sequence"))
+        .and_then(|s| s.as_u64())
+}
+
+// The timestamp claimed by the author, in ms since the epoch.
+pub fn asserted_timestamp(v: &Value) -> Option<f64> {
+    v.get("value")
+        .and_then(|v| v.get("timestamp"))
+        .and_then(|t| t.as_f64())
+}
+
+// When the local sbot received the message, in ms since the epoch.
+pub fn received_timestamp(v: &Value) -> Option<f64> {
+    v.get("timestamp").and_then(|t| t.as_f64())
+}
+
+// The content object, or None for private (encrypted string) content.
+pub fn content(v: &Value) -> Option<&Value> {
+    v.get("value")
+        .and_then(|v| v.get("content"))
+        .filter(|c| c.is_object())
+}
+
+pub fn content_type(v: &Value) -> Option<&str> {
+    content(v)
+        .and_then(|c| c.get("type"))
+        .and_then(|t| t.as_str())
+}
+
+pub fn is_private(v: &Value) -> bool {
+    v.get("value")
+        .and_then(|v| v.get("content"))
+        .is_some_and(|c| c.is_string())
+}
+
+pub fn channel(v: &Value) -> Option<&str> {
+    content(v)
+        .and_then(|c| c.get("channel"))
+        .and_then(|c| c.as_str())
+}
+
+// The channels a message is in, lowercased and without the `#`: by its
+// `channel` field, `#name` mentions, and `#name` hashtags in its text.
+pub fn channels(v: &Value) -> Vec<String> {
+    let content = match content(v) {
+        Some(c) => c,
+        None => return Vec::new(),
+    };
+    let mentioned = content
+        .get("mentions")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|m| m.get("link").and_then(|l| l.as_str()))
+        .filter(|l| l.starts_with('#'));
+    let tagged = content
+        .get("text")
+        .and_then(|t| t.as_str())
+        .into_iter()
+        .flat_map(|t| t.split(|c: char| !(c.is_alphanumeric() || c == '#' || c == '-' || c == '_')))
+        .filter(|w| w.starts_with('#'));
+    let mut channels: Vec<String> = channel(v)
+        .into_iter()
+        .chain(mentioned)
+        .chain(tagged)
+        .map(|c| c.trim_start_matches('#').to_ascii_lowercase())
+        .filter(|c| !c.is_empty())
+        .collect();
+    channels.sort_unstable();
+    channels.dedup();
+    channels
+}
+
+// Whether a message is in channel `name` (with or without the `#`, in any
+// case), as `channels` finds them.
+pub fn in_channel(v: &Value, name: &str) -> bool {
+    let name = name.trim_start_matches('#');
+    channels(v).iter().any(|c| c.eq_ignore_ascii_case(name))
+}
+
+// Feed ids in `content.mentions`, which may be a list of links or of `{ link }` objects.
+pub fn mentioned_feeds(content: &Value) -> impl Iterator<Item = &str> {
+    content
+        .get("mentions")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|m| {
+            m.as_str()
+                .or_else(|| m.get("link").and_then(|l| l.as_str()))
+        })
+        .filter(|l| l.starts_with('@'))
+}
+
+// Whether a message mentions feed `id`: in `content.mentions`, or inline in
+// its text.
+pub fn mentions(v: &Value, id: &str) -> bool {
+    let content = match content(v) {
+        Some(c) => c,
+        None => return false,
+    };
+    mentioned_feeds(content).any(|l| l == id)
+        || content
+            .get("text")
+            .and_then(|t| t.as_str())
+            .is_some_and(|t| t.contains(id))
+}
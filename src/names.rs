@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{stdout, BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::{json, Value};
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+use crate::uri;
+
+// A value from an `about` message, with the asserted time of the message,
+// so the latest one wins whatever order the log is in.
+type Latest = (f64, String);
+
+fn set(field: &mut Option<Latest>, time: f64, value: &str) {
+    if field.as_ref().is_none_or(|(t, _)| time >= *t) {
+        *field = Some((time, value.to_string()));
+    }
+}
+
+#[derive(Default)]
+struct Profile {
+    // What the feed calls itself.
+    name: Option<Latest>,
+    image: Option<Latest>,
+    // What other feeds call it, by their id.
+    named_by: HashMap<String, Latest>,
+}
+
+// Feeds' names and images, from `about` messages about them.
+#[derive(Default)]
+pub struct Names {
+    feeds: HashMap<String, Profile>,
+}
+
+impl Names {
+    pub fn scan(log: &OffsetLog<u32>) -> Result<Names, Error> {
+        let mut names = Names::default();
+        let mut progress = Progress::new(log.end());
+        for e in log.iter() {
+            progress.update(e.offset, || {
+                format!("Found {} names", units::count(names.feeds.len() as u64))
+            })?;
+            let v = match msg::parse(&e) {
+                Some(v) if msg::content_type(&v) == Some("about") => v,
+                _ => continue,
+            };
+            let content = msg::content(&v).unwrap();
+            let (author, about) = match (
+                msg::author(&v),
+                content.get("about").and_then(Value::as_str),
+            ) {
+                (Some(a), Some(about)) if about.starts_with('@') => (a, about),
+                _ => continue,
+            };
+            let time = msg::asserted_timestamp(&v).unwrap_or(0.0);
+            let profile = names.feeds.entry(about.to_string()).or_default();
+            if let Some(name) = content.get("name").and_then(Value::as_str) {
+                if author == about {
+                    set(&mut profile.name, time, name);
+                } else {
+                    let by = profile.named_by.entry(author.to_string()).or_default();
+                    if time >= by.0 {
+                        *by = (time, name.to_string());
+                    }
+                }
+            }
+            // An image is a blob id, or a `{ link }` object with one.
+            let image = content
+                .get("image")
+                .and_then(|i| i.as_str().or_else(|| i.get("link")?.as_str()));
+            if let (Some(image), true) = (image, author == about) {
+                set(&mut profile.image, time, image);
+            }
+        }
+        progress.finish(&format!(
+            "Found {} names",
+            units::count(names.feeds.len() as u64)
+        ))?;
+        Ok(names)
+    }
+
+    // The latest name a feed gave itself.
+    pub fn name(&self, id: &str) -> Option<&str> {
+        self.feeds.get(id)?.name.as_ref().map(|(_, n)| n.as_str())
+    }
+
+    // A line of JSON per feed with a name or image: `{ "id", "name", "image" }`,
+    // and with `others` a `named_by` map of the names other feeds gave it.
+    pub fn write(&self, others: bool) -> Result<(), Error> {
+        let stdout = stdout();
+        let mut out = BufWriter::new(stdout.lock());
+        let mut ids: Vec<&String> = self.feeds.keys().collect();
+        ids.sort_unstable();
+        let mut written: u64 = 0;
+        for id in ids {
+            let p = &self.feeds[id];
+            if p.name.is_none() && p.image.is_none() && (!others || p.named_by.is_empty()) {
+                continue;
+            }
+            let mut line = json!({
+                "id": uri::show(id),
+                "name": p.name.as_ref().map(|(_, n)| n),
+                "image": p.image.as_ref().map(|(_, i)| uri::show(i)),
+            });
+            if others {
+                let named_by: BTreeMap<String, &String> = p
+                    .named_by
+                    .iter()
+                    .map(|(by, (_, n))| (uri::show(by).into_owned(), n))
+                    .collect();
+                line["named_by"] = json!(named_by);
+            }
+            writeln!(out, "{}", line)?;
+            written += 1;
+        }
+        out.flush()?;
+        eprintln!("Named {} feeds.", units::count(written));
+        Ok(())
+    }
+}
+
+// `id`, followed by its name if there is one, for reports.
+pub fn label(names: Option<&Names>, id: &str) -> String {
+    match names.and_then(|n| n.name(id)) {
+        Some(name) => format!("{}  {}", uri::show(id), name),
+        None => uri::show(id).into_owned(),
+    }
+}
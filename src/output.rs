@@ -0,0 +1,217 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use fs2::FileExt;
+
+use crate::units;
+
+// Decide whether the output path may be written, moving an existing file
+// aside first when `backup` is set. Prints the reason and returns false if
+// the command should stop without touching the output.
+pub fn prepare(path: &str, inputs: &[&str], overwrite: bool, backup: bool) -> Result<bool, Error> {
+    let p = Path::new(path);
+    if !p.exists() {
+        return Ok(true);
+    }
+
+    for input in inputs {
+        if same_file(Path::new(input), p)? {
+            eprintln!(
+                "Output path `{}` is the same file as input `{}`.",
+                path, input
+            );
+            eprintln!("Writing to it would destroy the input before it's read.");
+            return Ok(false);
+        }
+    }
+
+    if !overwrite && !backup {
+        eprintln!("Output path `{}` exists.", path);
+        eprintln!("Use `--overwrite` option to overwrite, or `--backup` to move it aside.");
+        return Ok(false);
+    }
+
+    if let Some(reason) = in_use(p)? {
+        eprintln!("Output path `{}` is {}.", path, reason);
+        eprintln!("Refusing to replace a file that may be a live log.");
+        return Ok(false);
+    }
+
+    if backup {
+        let bak = backup_path(p);
+        fs::rename(p, &bak)?;
+        eprintln!("Moved existing `{}` to `{}`", path, bak.display());
+    }
+    Ok(true)
+}
+
+// Check that the filesystem holding `path` has room for `needed` bytes.
+// When `needed` is only an upper bound a shortfall is a warning; when it's
+// exact, prints why and returns false.
+pub fn check_space(path: &str, needed: u64, exact: bool) -> Result<bool, Error> {
+    let p = Path::new(path);
+    let dir = match p.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+
+    // An output that's about to be truncated gives its space back.
+    let reclaimed = fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+    let available = fs2::available_space(dir)? + reclaimed;
+    if available >= needed {
+        return Ok(true);
+    }
+
+    if exact {
+        eprintln!(
+            "Not enough free space for `{}`: need {}, {} available.",
+            path,
+            units::bytes(needed),
+            units::bytes(available)
+        );
+        return Ok(false);
+    }
+    eprintln!(
+        "Warning: output may need up to {}, but only {} are available at `{}`.",
+        units::bytes(needed),
+        units::bytes(available),
+        dir.display()
+    );
+    Ok(true)
+}
+
+// Create (or truncate) the output log.
+pub fn create_log(path: &str) -> Result<OffsetLog<u32>, Error> {
+    OffsetLog::<u32>::from_file(create_file(path)?)
+}
+
+// The output file itself, empty, for writing frames directly. It's locked
+// until dropped, and only emptied once locked, so a second command writing to
+// the same path fails instead of interleaving its writes.
+pub fn create_file(path: &str) -> Result<File, Error> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    try_lock(&file, path)?;
+    file.set_len(0)?;
+    Ok(file)
+}
+
+// Lock the file at `path` (creating it if needed) until the returned file
+// is dropped, for commands that append to a log rather than create it.
+pub fn lock(path: &str) -> Result<File, Error> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    try_lock(&file, path)?;
+    Ok(file)
+}
+
+// A file that's replaced rather than written in place (like a checkpoint
+// written to a temporary file and renamed) is locked through
+// `<path>.lock`, which stays put.
+pub fn lock_replaced(path: &str) -> Result<File, Error> {
+    lock(&format!("{}.lock", path))
+}
+
+fn try_lock(file: &File, path: &str) -> Result<(), Error> {
+    if file.try_lock_exclusive().is_err() {
+        return Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!("`{}` is in use by another command", path),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// Compares by device and inode where possible, so hard links and differently
+// spelled paths to the same file are caught too.
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let (a, b) = match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        _ => return Ok(false),
+    };
+    Ok(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> io::Result<bool> {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => Ok(a == b),
+        _ => Ok(false),
+    }
+}
+
+fn backup_path(p: &Path) -> PathBuf {
+    aside_path(p, "bak")
+}
+
+// `<p>.<unix time>.<ext>`, for keeping something next to `p`.
+pub fn aside_path(p: &Path, ext: &str) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut name = p.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.{}", secs, ext));
+    p.with_file_name(name)
+}
+
+// Returns a description of why the file looks like it's in use by another
+// process, if it does.
+pub fn in_use(p: &Path) -> io::Result<Option<&'static str>> {
+    let file = File::open(p)?;
+    match file.try_lock_exclusive() {
+        Ok(()) => file.unlock()?,
+        Err(_) => return Ok(Some("locked by another process")),
+    }
+
+    if open_elsewhere(p)? {
+        return Ok(Some("open in another process"));
+    }
+    Ok(None)
+}
+
+#[cfg(target_os = "linux")]
+fn open_elsewhere(p: &Path) -> io::Result<bool> {
+    let target = fs::canonicalize(p)?;
+    let me = std::process::id().to_string();
+
+    for proc_entry in fs::read_dir("/proc")? {
+        let proc_entry = proc_entry?;
+        let name = proc_entry.file_name();
+        let name = name.to_string_lossy();
+        if name == me || !name.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        // Other users' processes aren't readable; skip them.
+        let fds = match fs::read_dir(proc_entry.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        for fd in fds.flatten() {
+            if matches!(fs::read_link(fd.path()), Ok(l) if l == target) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_elsewhere(_p: &Path) -> io::Result<bool> {
+    Ok(false)
+}
@@ -0,0 +1,154 @@
+use std::fs;
+use std::io::{stdout, BufWriter, Write};
+use std::thread;
+use std::time::Duration;
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::{json, to_string_pretty, Value};
+
+use crate::annotations::Annotations;
+use crate::frame::{self, FrameError};
+use crate::msg;
+use crate::render;
+use crate::units;
+
+// Which message `get` looks for.
+pub enum Lookup<'a> {
+    Key(&'a str),
+    Sequence(&'a str, u64),
+    Offset(u64),
+}
+
+// How often `tail --follow` checks the log for new entries.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Prints entries for `head` and `tail`: rendered like the viewer, as pretty
+// JSON with `raw`, or with `json` as NDJSON, `{ "offset", "entry" }`.
+pub struct Printer<'a> {
+    pub annotations: &'a Annotations,
+    pub raw: bool,
+    pub json: bool,
+}
+
+impl Printer<'_> {
+    fn print<W: Write>(&self, out: &mut W, offset: u64, data: &[u8]) -> Result<(), Error> {
+        let v: Option<Value> = serde_json::from_slice(data).ok();
+        let annotation = v.as_ref().and_then(|v| self.annotations.get(msg::key(v)?));
+        if self.json {
+            let mut line = json!({ "offset": offset, "entry": v });
+            if let Some(a) = annotation {
+                line["annotations"] = serde_json::to_value(a)?;
+            }
+            writeln!(out, "{}", line)?;
+            return Ok(());
+        }
+        writeln!(out, "Offset: {}", offset)?;
+        if let Some(a) = annotation {
+            writeln!(out, "Annotated: {}", a.summary())?;
+        }
+        let width = termion::terminal_size().map_or(80, |(w, _)| w as usize);
+        match v {
+            Some(v) if self.raw => writeln!(out, "{}", to_string_pretty(&v)?)?,
+            Some(v) => writeln!(out, "{}", render::render(&v, width, false).trim_end())?,
+            None => writeln!(out, "(not JSON, {})", units::bytes(data.len() as u64))?,
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+pub fn head(log: &OffsetLog<u32>, n: usize, printer: &Printer) -> Result<(), Error> {
+    let stdout = stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for e in log.iter().take(n) {
+        printer.print(&mut out, e.offset, &e.data)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// Print the last `n` entries, read backwards from the end of the file so a
+// huge log isn't walked. With `follow`, keep printing entries as they're
+// appended, eg. by a running sbot, until interrupted.
+pub fn tail(path: &str, n: usize, follow: bool, printer: &Printer) -> Result<(), Error> {
+    let stdout = stdout();
+    let mut out = BufWriter::new(stdout.lock());
+
+    let last = frame::last_frames(path, n.max(1))?;
+    for f in last.iter().take(n).rev() {
+        printer.print(&mut out, f.offset, &f.data)?;
+    }
+    out.flush()?;
+    if !follow {
+        return Ok(());
+    }
+
+    let mut offset = last.first().map_or(0, |f| f.next_offset());
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if fs::metadata(path)?.len() < offset {
+            eprintln!("`{}` got shorter; following it from the start.", path);
+            offset = 0;
+        }
+        for f in frame::frames_from(path, offset)? {
+            match f {
+                Ok(f) => {
+                    printer.print(&mut out, f.offset, &f.data)?;
+                    offset = f.next_offset();
+                }
+                // Still being written; it'll be complete next time.
+                Err(FrameError::Truncated { .. }) => break,
+                Err(FrameError::Io(e)) => return Err(e.into()),
+                Err(e) => {
+                    eprintln!("Stopped reading `{}`: {}", path, e);
+                    return Ok(());
+                }
+            }
+        }
+        out.flush()?;
+    }
+}
+
+// Print the entry `lookup` finds, or return false if there isn't one. A key
+// or sequence number means reading the log up to the message; an offset is
+// read directly, and must be where an entry starts.
+pub fn get(
+    path: &str,
+    log: &OffsetLog<u32>,
+    lookup: &Lookup,
+    printer: &Printer,
+) -> Result<bool, Error> {
+    let stdout = stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let found = match *lookup {
+        Lookup::Offset(offset) => match frame::frames_from(path, offset)?.next() {
+            Some(Ok(f)) => Some((f.offset, f.data)),
+            Some(Err(e)) => {
+                eprintln!("No entry at offset {}: {}", offset, e);
+                None
+            }
+            None => None,
+        },
+        Lookup::Key(key) => log
+            .iter()
+            .find(|e| msg::parse(e).is_some_and(|v| msg::key(&v) == Some(key)))
+            .map(|e| (e.offset, e.data)),
+        Lookup::Sequence(feed, seq) => log
+            .iter()
+            .find(|e| {
+                msg::parse(e).is_some_and(|v| {
+                    msg::author(&v) == Some(feed) && msg::sequence(&v) == Some(seq)
+                })
+            })
+            .map(|e| (e.offset, e.data)),
+    };
+    match found {
+        Some((offset, data)) => {
+            printer.print(&mut out, offset, &data)?;
+            out.flush()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,57 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+const INTERVAL: Duration = Duration::from_millis(100);
+
+// A single self-overwriting status line on stderr, redrawn at most every
+// INTERVAL no matter how often `update` is called.
+pub struct Progress {
+    total: u64,
+    last: Option<Instant>,
+}
+
+impl Progress {
+    // `total` is the position that corresponds to 100%, e.g. the input log length.
+    pub fn new(total: u64) -> Progress {
+        Progress { total, last: None }
+    }
+
+    // Report `position` (out of `total`); `status` is only called when the
+    // line is actually redrawn.
+    pub fn update<F>(&mut self, position: u64, status: F) -> io::Result<()>
+    where
+        F: FnOnce() -> String,
+    {
+        let now = Instant::now();
+        match self.last {
+            Some(last) if now.duration_since(last) < INTERVAL => Ok(()),
+            _ => {
+                self.last = Some(now);
+                self.draw(position, &status())
+            }
+        }
+    }
+
+    // Draw the final state and end the line.
+    pub fn finish(&mut self, status: &str) -> io::Result<()> {
+        self.stop(self.total, status)
+    }
+
+    // Like `finish`, for when the work stops short at `position`.
+    pub fn stop(&mut self, position: u64, status: &str) -> io::Result<()> {
+        self.draw(position, status)?;
+        eprintln!();
+        Ok(())
+    }
+
+    fn draw(&self, position: u64, status: &str) -> io::Result<()> {
+        let pct = (100 * position.min(self.total))
+            .checked_div(self.total)
+            .unwrap_or(100);
+
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        write!(handle, "\rProgress: {}%\t{}", pct, status)?;
+        handle.flush()
+    }
+}
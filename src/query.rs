@@ -0,0 +1,446 @@
+use std::io::{self, stdout, BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::Value;
+
+use crate::progress::Progress;
+use crate::units;
+
+// A small jq-like language, evaluated against each entry:
+//
+//   query := stage ('|' stage)*
+//   stage := 'select' expr      keep the entry if expr is truthy
+//          | expr               replace the entry with expr's value
+//   expr  := expr 'or' expr | expr 'and' expr | 'not' expr
+//          | term (('==' | '!=' | '<' | '<=' | '>' | '>=') term)?
+//   term  := path | "string" | number | true | false | null | '(' expr ')'
+//   path  := '.' | ('.' name | '[' index ']')+    eg. .value.content.mentions[0]
+//
+// As in jq, only `false` and `null` are falsy, and a missing field is `null`.
+pub struct Query(Vec<Stage>);
+
+enum Stage {
+    Select(Expr),
+    Map(Expr),
+}
+
+enum Expr {
+    Path(Vec<Step>),
+    Literal(Value),
+    Cmp(Box<Expr>, Op, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+enum Step {
+    Field(String),
+    Index(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Dot,
+    // `.name`, written without a space.
+    Field(String),
+    Name(String),
+    Literal(Value),
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Pipe,
+    Op(&'static str),
+}
+
+fn invalid(msg: String) -> Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg).into()
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let (token, len) = match c {
+            '.' => {
+                let len = rest[1..]
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len() - 1);
+                match &rest[1..1 + len] {
+                    "" => (Token::Dot, 1),
+                    name => (Token::Field(name.to_string()), 1 + len),
+                }
+            }
+            '[' => (Token::LBracket, 1),
+            ']' => (Token::RBracket, 1),
+            '(' => (Token::LParen, 1),
+            ')' => (Token::RParen, 1),
+            '|' => (Token::Pipe, 1),
+            '"' => {
+                // Let serde_json handle the escapes.
+                let mut de = serde_json::Deserializer::from_str(rest).into_iter::<Value>();
+                let v = de
+                    .next()
+                    .and_then(|v| v.ok())
+                    .ok_or_else(|| invalid(format!("unterminated string: {}", rest)))?;
+                (Token::Literal(v), de.byte_offset())
+            }
+            '=' | '!' | '<' | '>' => {
+                let op = ["==", "!=", "<=", ">=", "<", ">"]
+                    .iter()
+                    .find(|op| rest.starts_with(*op))
+                    .ok_or_else(|| invalid(format!("unknown operator at: {}", rest)))?;
+                (Token::Op(op), op.len())
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_digit() || "-+.eE".contains(c)))
+                    .unwrap_or(rest.len());
+                let v = serde_json::from_str(&rest[..len])
+                    .map_err(|_| invalid(format!("bad number: {}", &rest[..len])))?;
+                (Token::Literal(v), len)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                let token = match &rest[..len] {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    name => Token::Name(name.to_string()),
+                };
+                (token, len)
+            }
+            _ => return Err(invalid(format!("unexpected `{}`", c))),
+        };
+        tokens.push(token);
+        rest = &rest[len..];
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1)
+    }
+
+    fn eat_name(&mut self, name: &str) -> bool {
+        let is = matches!(self.peek(), Some(Token::Name(n)) if n == name);
+        if is {
+            self.pos += 1;
+        }
+        is
+    }
+
+    fn expect(&mut self, t: Token) -> Result<(), Error> {
+        match self.next() {
+            Some(got) if *got == t => Ok(()),
+            got => Err(invalid(format!("expected {:?}, found {:?}", t, got))),
+        }
+    }
+
+    fn stage(&mut self) -> Result<Stage, Error> {
+        if self.eat_name("select") {
+            Ok(Stage::Select(self.or()?))
+        } else {
+            Ok(Stage::Map(self.or()?))
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        let mut e = self.and()?;
+        while self.eat_name("or") {
+            e = Expr::Or(Box::new(e), Box::new(self.and()?));
+        }
+        Ok(e)
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        let mut e = self.not()?;
+        while self.eat_name("and") {
+            e = Expr::And(Box::new(e), Box::new(self.not()?));
+        }
+        Ok(e)
+    }
+
+    fn not(&mut self) -> Result<Expr, Error> {
+        if self.eat_name("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        let left = self.term()?;
+        let op = match self.peek() {
+            Some(Token::Op(op)) => match *op {
+                "==" => Op::Eq,
+                "!=" => Op::Ne,
+                "<" => Op::Lt,
+                "<=" => Op::Le,
+                ">" => Op::Gt,
+                _ => Op::Ge,
+            },
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        Ok(Expr::Cmp(Box::new(left), op, Box::new(self.term()?)))
+    }
+
+    fn term(&mut self) -> Result<Expr, Error> {
+        match self.next() {
+            Some(Token::Literal(v)) => Ok(Expr::Literal(v.clone())),
+            Some(Token::LParen) => {
+                let e = self.or()?;
+                self.expect(Token::RParen)?;
+                Ok(e)
+            }
+            Some(Token::Dot) | Some(Token::Field(_)) => {
+                self.pos -= 1;
+                self.path()
+            }
+            got => Err(invalid(format!("expected a value, found {:?}", got))),
+        }
+    }
+
+    fn path(&mut self) -> Result<Expr, Error> {
+        let mut steps = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Field(f)) => {
+                    steps.push(Step::Field(f.clone()));
+                    self.pos += 1;
+                }
+                Some(Token::Dot) => {
+                    self.pos += 1;
+                    // `."quoted key"`, for keys that aren't plain names.
+                    if let Some(Token::Literal(Value::String(s))) = self.peek() {
+                        steps.push(Step::Field(s.clone()));
+                        self.pos += 1;
+                    }
+                }
+                Some(Token::LBracket) => {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(Token::Literal(Value::Number(n))) if n.is_u64() => {
+                            steps.push(Step::Index(n.as_u64().unwrap() as usize))
+                        }
+                        got => return Err(invalid(format!("expected an index, found {:?}", got))),
+                    }
+                    self.expect(Token::RBracket)?;
+                }
+                _ => return Ok(Expr::Path(steps)),
+            }
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(s: &str) -> Result<Query, Error> {
+        let mut p = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let mut stages = vec![p.stage()?];
+        while p.peek() == Some(&Token::Pipe) {
+            p.pos += 1;
+            stages.push(p.stage()?);
+        }
+        match p.peek() {
+            None => Ok(Query(stages)),
+            Some(t) => Err(invalid(format!("unexpected {:?}", t))),
+        }
+    }
+
+    // The query's output for entry `v`, or None if a `select` dropped it.
+    fn run(&self, mut v: Value) -> Option<Value> {
+        for stage in &self.0 {
+            match stage {
+                Stage::Select(e) => {
+                    if !truthy(&e.eval(&v)) {
+                        return None;
+                    }
+                }
+                Stage::Map(e) => v = e.eval(&v),
+            }
+        }
+        Some(v)
+    }
+}
+
+fn truthy(v: &Value) -> bool {
+    !matches!(v, Value::Null | Value::Bool(false))
+}
+
+impl Expr {
+    fn eval(&self, v: &Value) -> Value {
+        match self {
+            Expr::Path(steps) => steps
+                .iter()
+                .try_fold(v, |v, step| match step {
+                    Step::Field(f) => v.get(f),
+                    Step::Index(i) => v.get(i),
+                })
+                .cloned()
+                .unwrap_or(Value::Null),
+            Expr::Literal(l) => l.clone(),
+            Expr::Cmp(a, op, b) => Value::Bool(compare(&a.eval(v), *op, &b.eval(v))),
+            Expr::And(a, b) => Value::Bool(truthy(&a.eval(v)) && truthy(&b.eval(v))),
+            Expr::Or(a, b) => Value::Bool(truthy(&a.eval(v)) || truthy(&b.eval(v))),
+            Expr::Not(e) => Value::Bool(!truthy(&e.eval(v))),
+        }
+    }
+}
+
+// Numbers compare by value and strings lexically; values of other or
+// differing types are only ever equal or not.
+fn compare(a: &Value, op: Op, b: &Value) -> bool {
+    use std::cmp::Ordering;
+    let ord = match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64().partial_cmp(&y.as_f64()),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ if a == b => Some(Ordering::Equal),
+        _ => None,
+    };
+    match op {
+        Op::Eq => ord == Some(Ordering::Equal),
+        Op::Ne => ord != Some(Ordering::Equal),
+        Op::Lt => ord == Some(Ordering::Less),
+        Op::Le => matches!(ord, Some(Ordering::Less) | Some(Ordering::Equal)),
+        Op::Gt => ord == Some(Ordering::Greater),
+        Op::Ge => matches!(ord, Some(Ordering::Greater) | Some(Ordering::Equal)),
+    }
+}
+
+// Print the query's output for each entry as a line of JSON.
+pub fn query(log: &OffsetLog<u32>, q: &Query) -> Result<(), Error> {
+    let stdout = stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut progress = Progress::new(log.end());
+    let mut results: u64 = 0;
+
+    for e in log.iter() {
+        progress.update(e.offset, || format!("{} results", units::count(results)))?;
+        let v: Value = match serde_json::from_slice(&e.data) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if let Some(r) = q.run(v) {
+            writeln!(out, "{}", r)?;
+            results += 1;
+        }
+    }
+    out.flush()?;
+    progress.finish(&format!("{} results", units::count(results)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(q: &str, v: Value) -> Option<Value> {
+        Query::parse(q).unwrap().run(v)
+    }
+
+    fn msg() -> Value {
+        json!({
+            "key": "%abc",
+            "value": {
+                "sequence": 10,
+                "content": { "type": "post", "text": "hi", "mentions": ["@a"], "root": null }
+            }
+        })
+    }
+
+    #[test]
+    fn missing_fields_are_null() {
+        assert_eq!(run(".value.content.channel", msg()), Some(Value::Null));
+        assert_eq!(run(".value.nope.deeper", msg()), Some(Value::Null));
+        assert_eq!(run(".value.content.mentions[1]", msg()), Some(Value::Null));
+        assert_eq!(run(".value.content.text[0]", msg()), Some(Value::Null));
+        assert_eq!(run(".value.content.mentions[0]", msg()), Some(json!("@a")));
+        assert_eq!(run(r#"."key""#, msg()), Some(json!("%abc")));
+        assert_eq!(run(".", msg()), Some(msg()));
+    }
+
+    #[test]
+    fn numbers_and_strings_dont_compare() {
+        assert_eq!(run(r#".value.sequence == "10""#, msg()), Some(json!(false)));
+        assert_eq!(run(r#".value.sequence != "10""#, msg()), Some(json!(true)));
+        assert_eq!(run(r#".value.sequence < "9""#, msg()), Some(json!(false)));
+        assert_eq!(run(r#".value.sequence >= "9""#, msg()), Some(json!(false)));
+        assert_eq!(run(".value.sequence > 9", msg()), Some(json!(true)));
+        assert_eq!(run(".value.sequence == 10.0", msg()), Some(json!(true)));
+        // Strings compare lexically.
+        assert_eq!(run(r#""10" < "9""#, msg()), Some(json!(true)));
+        assert_eq!(run(".value.content.root == null", msg()), Some(json!(true)));
+    }
+
+    #[test]
+    fn only_false_and_null_are_falsy() {
+        for v in &[json!(0), json!(""), json!([]), json!({}), json!(true)] {
+            assert!(run("select .x", json!({ "x": v })).is_some(), "{}", v);
+        }
+        for v in &[json!(false), json!(null)] {
+            assert!(run("select .x", json!({ "x": v })).is_none(), "{}", v);
+        }
+        assert!(run("select .missing", msg()).is_none());
+        assert!(run("select not .missing", msg()).is_some());
+        assert!(run("select .key and .value.content.root", msg()).is_none());
+        assert!(run("select .value.content.root or .key", msg()).is_some());
+    }
+
+    #[test]
+    fn pipes_run_in_order() {
+        let q = r#"select .value.content.type == "post" | .value.content | .text"#;
+        assert_eq!(run(q, msg()), Some(json!("hi")));
+        assert!(run(r#".value | select .sequence < 5"#, msg()).is_none());
+        assert!(run(
+            r#"select (.value.sequence > 5 or .missing) and not (.key == "%x")"#,
+            msg()
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn parse_errors() {
+        for q in &[
+            r#"select .value.content.type == "post"#,
+            ".value.content.mentions[x]",
+            ".value.content.mentions[-1]",
+            ".value.content.mentions[1.5]",
+            ".value.content.mentions[0",
+            ".value ==",
+            ".value = 1",
+            "(.value",
+            ".value )",
+            ".value $",
+        ] {
+            assert!(Query::parse(q).is_err(), "{}", q);
+        }
+    }
+}
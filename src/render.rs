@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+
+use serde_json::{to_string_pretty, Value};
+use termion::style;
+
+use crate::msg;
+
+// A human-readable rendering of a flume entry for the viewer: a header line
+// with author, sequence and date, then the content, written out for the
+// well-known types and as JSON for the rest. Lines are wrapped to `width`.
+// With `markdown`, post text is rendered with terminal styles.
+pub fn render(v: &Value, width: usize, markdown: bool) -> String {
+    let author = msg::author(v).unwrap_or("<unknown author>");
+    let mut out = format!(
+        "{}  #{}  {}\n",
+        author,
+        msg::sequence(v).unwrap_or(0),
+        msg::asserted_timestamp(v)
+            .map(format_date)
+            .unwrap_or_default()
+    );
+    if let Some(key) = msg::key(v) {
+        out.push_str(&format!("{}\n", key));
+    }
+    out.push('\n');
+
+    let content = match msg::content(v) {
+        Some(c) => c,
+        None if msg::is_private(v) => {
+            out.push_str("(encrypted message)\n");
+            return out;
+        }
+        None => {
+            out.push_str(&to_string_pretty(v).unwrap());
+            return out;
+        }
+    };
+    let field = |name| content.get(name).and_then(|f: &Value| f.as_str());
+
+    match msg::content_type(v) {
+        Some("post") => {
+            if let Some(channel) = field("channel") {
+                out.push_str(&format!("in #{}\n", channel));
+            }
+            if let Some(root) = field("root") {
+                out.push_str(&format!("in reply to {}\n", root));
+            }
+            out.push('\n');
+            let text = field("text").unwrap_or("");
+            if markdown {
+                out.push_str(&wrap(
+                    &render_markdown(text, &mention_names(content)),
+                    width,
+                ));
+            } else {
+                out.push_str(&wrap(text, width));
+            }
+        }
+        Some("contact") => {
+            let verb = match (
+                content.get("following").and_then(|f| f.as_bool()),
+                content.get("blocking").and_then(|b| b.as_bool()),
+            ) {
+                (_, Some(true)) => "blocks",
+                (Some(true), _) => "follows",
+                (Some(false), _) => "unfollows",
+                (_, Some(false)) => "unblocks",
+                _ => "mentions contact",
+            };
+            out.push_str(&format!(
+                "{} {} {}\n",
+                author,
+                verb,
+                field("contact").unwrap_or("<nobody>")
+            ));
+        }
+        Some("vote") => {
+            let vote = content.get("vote");
+            let link = vote
+                .and_then(|v| v.get("link"))
+                .and_then(|l| l.as_str())
+                .unwrap_or("<nothing>");
+            let verb = match vote.and_then(|v| v.get("value")).and_then(|v| v.as_i64()) {
+                Some(1) => "liked".to_string(),
+                Some(0) => "unliked".to_string(),
+                Some(n) => format!("voted {} on", n),
+                None => "voted on".to_string(),
+            };
+            out.push_str(&format!("{} {} {}\n", author, verb, link));
+            if let Some(expression) = vote
+                .and_then(|v| v.get("expression"))
+                .and_then(|e| e.as_str())
+            {
+                out.push_str(&format!("({})\n", expression));
+            }
+        }
+        Some("about") => {
+            let about = field("about").unwrap_or("<nobody>");
+            if about == author {
+                out.push_str("updated their profile:\n");
+            } else {
+                out.push_str(&format!("updated the profile of {}:\n", about));
+            }
+            if let Some(fields) = content.as_object() {
+                for (k, v) in fields {
+                    if k == "type" || k == "about" {
+                        continue;
+                    }
+                    let v = match v {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    out.push_str(&wrap(&format!("  {}: {}", k, v), width));
+                }
+            }
+        }
+        _ => out.push_str(&to_string_pretty(content).unwrap()),
+    }
+    out
+}
+
+// Word-wrap each line of `text` to `width` columns, keeping its indentation.
+fn wrap(text: &str, width: usize) -> String {
+    let mut out = String::new();
+    for line in text.lines() {
+        let words = line.trim_start();
+        let indent = &line[..line.len() - words.len()];
+        out.push_str(indent);
+        let mut col = indent.len();
+        let mut first = true;
+        for word in words.split(' ') {
+            let len = visible_len(word);
+            if !first && col + 1 + len > width {
+                out.push('\n');
+                out.push_str(indent);
+                col = indent.len();
+            } else if !first {
+                out.push(' ');
+                col += 1;
+            }
+            out.push_str(word);
+            col += len;
+            first = false;
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// Length in columns, not counting terminal escape sequences.
+fn visible_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut in_escape = false;
+    for c in s.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            c if in_escape => in_escape = !c.is_ascii_alphabetic(),
+            _ => len += 1,
+        }
+    }
+    len
+}
+
+// Feed ids mentioned by a post, with the names it gives them:
+// `mentions: [{ link: "@...", name: "alice" }]`. Clients write the names they
+// knew when posting, so no about index is needed.
+fn mention_names(content: &Value) -> HashMap<&str, &str> {
+    content
+        .get("mentions")
+        .and_then(|m| m.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|m| {
+            let link = m.get("link").and_then(|l| l.as_str())?;
+            let name = m.get("name").and_then(|n| n.as_str())?;
+            Some((link, name.trim_start_matches('@')))
+        })
+        .filter(|(link, _)| link.starts_with('@'))
+        .collect()
+}
+
+// A small subset of markdown, for reading posts in a terminal: headings,
+// quotes and list items, `**strong**`, `*emphasis*` and links, with link
+// targets dimmed and mentioned feed ids replaced by their names.
+fn render_markdown(text: &str, names: &HashMap<&str, &str>) -> String {
+    let mut out = String::new();
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if let Some(heading) = trimmed.strip_prefix('#').map(|h| h.trim_start_matches('#')) {
+            if heading.starts_with(' ') {
+                out.push_str(&format!(
+                    "{}{}{}\n",
+                    style::Bold,
+                    inline(heading.trim(), style::Bold.as_ref(), names),
+                    style::Reset
+                ));
+                continue;
+            }
+        }
+        if let Some(quote) = trimmed.strip_prefix('>') {
+            out.push_str(&format!(
+                "{}│ {}{}\n",
+                style::Faint,
+                inline(quote.trim_start(), style::Faint.as_ref(), names),
+                style::Reset
+            ));
+            continue;
+        }
+        let indent = &line[..line.len() - trimmed.len()];
+        let item = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|b| trimmed.strip_prefix(b));
+        match item {
+            Some(item) => out.push_str(&format!("{}  • {}\n", indent, inline(item, "", names))),
+            None => out.push_str(&format!("{}{}\n", indent, inline(trimmed, "", names))),
+        }
+    }
+    out
+}
+
+// `base` is the style of the surrounding block, restored after each change.
+fn inline(text: &str, base: &str, names: &HashMap<&str, &str>) -> String {
+    let mut out = String::new();
+    let mut bold = false;
+    let mut italic = false;
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("**") || rest.starts_with("__") {
+            bold = !bold;
+            restyle(&mut out, base, bold, italic);
+            rest = &rest[2..];
+        } else if c == '*' || (c == '_' && (italic || out.is_empty() || out.ends_with(' '))) {
+            italic = !italic;
+            restyle(&mut out, base, bold, italic);
+            rest = &rest[1..];
+        } else if let Some((label, target, len)) = link(rest) {
+            match names.get(target) {
+                Some(name) => out.push_str(&format!("{}@{}", style::Bold, name)),
+                None if target.starts_with('@') => {
+                    out.push_str(&format!("{}{}", style::Bold, label))
+                }
+                None => out.push_str(&format!("{} {}({})", label, style::Faint, target)),
+            }
+            restyle(&mut out, base, bold, italic);
+            rest = &rest[len..];
+        } else if c == '@' {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == ')' || c == ',')
+                .unwrap_or(rest.len());
+            let id = rest[..end].trim_end_matches(['.', ':']);
+            match names.get(id) {
+                Some(name) => out.push_str(&format!("@{}", name)),
+                None => out.push_str(id),
+            }
+            rest = &rest[id.len()..];
+        } else if rest.starts_with("http://") || rest.starts_with("https://") {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            out.push_str(&format!("{}{}", style::Faint, &rest[..end]));
+            restyle(&mut out, base, bold, italic);
+            rest = &rest[end..];
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if bold || italic {
+        out.push_str(style::Reset.as_ref());
+    }
+    out
+}
+
+// `[label](target)` at the start of `s`: the label, the target and the length.
+fn link(s: &str) -> Option<(&str, &str, usize)> {
+    let s = s.strip_prefix('[')?;
+    let label_end = s.find("](")?;
+    let label = &s[..label_end];
+    if label.contains('[') {
+        return None;
+    }
+    let after = &s[label_end + 2..];
+    let target_end = after.find(')')?;
+    Some((
+        label,
+        &after[..target_end],
+        1 + label_end + 2 + target_end + 1,
+    ))
+}
+
+// Switch back to the block's style, then on to whatever styles are active.
+fn restyle(out: &mut String, base: &str, bold: bool, italic: bool) {
+    out.push_str(style::Reset.as_ref());
+    out.push_str(base);
+    if bold {
+        out.push_str(style::Bold.as_ref());
+    }
+    if italic {
+        out.push_str(style::Italic.as_ref());
+    }
+}
+
+// Howard Hinnant's civil_from_days: days since the epoch as (year, month, day).
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Howard Hinnant's days_from_civil, the inverse of `civil_from_days`.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// `ms` since the epoch as `YYYY-MM-DD HH:MM UTC`.
+pub fn format_date(ms: f64) -> String {
+    let secs = (ms / 1000.0) as i64;
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60
+    )
+}
+
+// The inverse of `format_date`, for dates given on the command line:
+// `YYYY-MM-DD`, optionally followed by ` HH:MM[:SS]` or `THH:MM[:SS][Z]`
+// (always UTC), or a number of ms since the epoch.
+pub fn parse_date(s: &str) -> Option<f64> {
+    if let Ok(ms) = s.parse::<f64>() {
+        return Some(ms);
+    }
+    let s = s.trim_end_matches(" UTC").trim_end_matches('Z');
+    let (date, time) = match s.find([' ', 'T']) {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, "00:00"),
+    };
+    let num = |p: Option<&str>| p.and_then(|p| p.parse::<i64>().ok());
+    let mut d = date.splitn(3, '-');
+    let (year, month, day) = (num(d.next())?, num(d.next())?, num(d.next())?);
+    let mut t = time.splitn(3, ':');
+    let (hour, min) = (num(t.next())?, num(t.next())?);
+    let sec = match t.next() {
+        Some(sec) => num(Some(sec))?,
+        None => 0,
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    Some(((days * 86400 + hour * 3600 + min * 60 + sec) * 1000) as f64)
+}
@@ -0,0 +1,112 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::Path;
+
+use flumedb::flume_log::{Error, FlumeLog};
+use flumedb::offset_log::OffsetLog;
+
+use crate::frame::{self, RawFrame};
+use crate::input;
+use crate::output;
+use crate::progress::Progress;
+use crate::units;
+
+// Walk the frames of the log at `path`, passing each good one to `f`, and
+// return where the good frames end. Prints what broke the framing, if
+// anything did.
+fn walk_good_frames<F>(path: &Path, mut f: F) -> Result<u64, Error>
+where
+    F: FnMut(&RawFrame) -> Result<(), Error>,
+{
+    let len = path.metadata()?.len();
+    let mut progress = Progress::new(len);
+    let mut end = 0;
+    let mut count: u64 = 0;
+
+    for frame in frame::frames(path)? {
+        match frame {
+            Ok(frame) => {
+                f(&frame)?;
+                end = frame.next_offset();
+                count += 1;
+                progress.update(frame.offset, || {
+                    format!("Kept {} entries", units::count(count))
+                })?;
+            }
+            Err(frame::FrameError::Io(e)) => return Err(e.into()),
+            Err(e) => {
+                progress.stop(end, &format!("Kept {} entries", units::count(count)))?;
+                println!("{}", e);
+                println!(
+                    "Kept {} entries ({}), dropped {} from offset {}.",
+                    units::count(count),
+                    units::bytes(end),
+                    units::bytes(len - end),
+                    end
+                );
+                return Ok(end);
+            }
+        }
+    }
+    progress.finish(&format!("Kept {} entries", units::count(count)))?;
+    println!(
+        "The framing is sound; kept all {} entries.",
+        units::count(count)
+    );
+    Ok(end)
+}
+
+// Copy every structurally valid entry of the log at `path`, up to the first
+// corruption, to `out_log`.
+pub fn repair(path: &str, mut out_log: OffsetLog<u32>) -> Result<(), Error> {
+    walk_good_frames(Path::new(path), |f| {
+        out_log.append(&f.data)?;
+        Ok(())
+    })?;
+    Ok(())
+}
+
+// Replace the log at `path` with a copy that ends after its last good entry.
+// The copy is written next to it and renamed over it; the original is moved
+// to `<path>.<unix time>.bak` untouched, so the repair can be undone by moving
+// it back.
+pub fn repair_in_place(path: &str) -> Result<(), Error> {
+    let p = Path::new(path);
+    if let Some(reason) = output::in_use(p)? {
+        eprintln!("`{}` is {}.", path, reason);
+        eprintln!("Stop sbot before repairing its log in place.");
+        return Ok(());
+    }
+
+    let end = walk_good_frames(p, |_| Ok(()))?;
+    let file = File::open(p)?;
+    if end == file.metadata()?.len() {
+        return Ok(());
+    }
+
+    let tmp_path = output::aside_path(p, "repair");
+    if !output::check_space(&tmp_path.to_string_lossy(), end, true)? {
+        return Ok(());
+    }
+    let mut tmp = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp_path)?;
+    tmp.set_permissions(file.metadata()?.permissions())?;
+    io::copy(&mut file.take(end), &mut tmp)?;
+    tmp.sync_all()?;
+
+    let bak_path = output::aside_path(p, "bak");
+    fs::rename(p, &bak_path)?;
+    fs::rename(&tmp_path, p)?;
+    println!(
+        "Replaced `{}` with the repaired log; the original is in `{}`.",
+        path,
+        bak_path.display()
+    );
+
+    if let Some(problem) = input::check(p)? {
+        eprintln!("After repairing: {}", problem);
+    }
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use std::fs;
+
+use flumedb::flume_log::Error;
+use minijinja::Environment;
+use serde_json::Value;
+
+// How a command prints its report: as text, as the JSON of its report data,
+// or through a user's template (minijinja, ie. Jinja2 syntax) rendered with
+// that same data, so the wording and layout can be changed or translated
+// without touching the code.
+pub enum Format {
+    Text,
+    Json,
+    Template(String),
+}
+
+impl Format {
+    // From a command's `--json` and `--template <path>` options.
+    pub fn from_args(json: bool, template: Option<&str>) -> Result<Format, Error> {
+        Ok(match template {
+            Some(path) => Format::Template(fs::read_to_string(path)?),
+            None if json => Format::Json,
+            None => Format::Text,
+        })
+    }
+}
+
+// Print `data` as JSON or through the template. Text output is up to each
+// command.
+pub fn emit(data: &Value, format: &Format) -> Result<(), Error> {
+    match format {
+        Format::Text => unreachable!("text reports are printed by their command"),
+        Format::Json => println!("{}", data),
+        Format::Template(source) => {
+            let mut env = Environment::new();
+            env.add_template("report", source)?;
+            print!("{}", env.get_template("report")?.render(data)?);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::io::{stdout, BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::{json, Map, Value};
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+// Record types whose state is built up by `about` updates.
+pub const KINDS: &[&str] = &["gathering", "blog", "profile"];
+
+// A gathering, blog post or profile after replaying its updates. Only the
+// record's author can update it; an update with a `tombstone` field deletes
+// the record, and a later one with `tombstone: null` brings it back.
+struct Record {
+    kind: &'static str,
+    author: String,
+    state: Map<String, Value>,
+    tombstone: Option<Value>,
+    updated: f64,
+}
+
+struct Update {
+    target: String,
+    author: String,
+    time: f64,
+    content: Value,
+}
+
+// Print the latest state of each record of the given kinds as a line of
+// JSON: `{ "key", "type", "author", "updated", "state" }`. Tombstoned records
+// are left out unless `tombstoned`, and then have a `tombstone` field.
+pub fn resolve(log: &OffsetLog<u32>, kinds: &[&str], tombstoned: bool) -> Result<(), Error> {
+    let mut progress = Progress::new(log.end());
+    let mut records: HashMap<String, Record> = HashMap::new();
+    // Updates can come before their record in the log, so they're applied
+    // once it's all been read.
+    let mut updates = Vec::new();
+
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} records", units::count(records.len() as u64))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let (author, content) = match (msg::author(&v), msg::content(&v)) {
+            (Some(a), Some(c)) => (a, c),
+            _ => continue,
+        };
+        let time = msg::asserted_timestamp(&v).unwrap_or(0.0);
+        match msg::content_type(&v) {
+            Some("about") => {
+                if let Some(target) = content.get("about").and_then(|a| a.as_str()) {
+                    updates.push(Update {
+                        target: target.to_string(),
+                        author: author.to_string(),
+                        time,
+                        content: content.clone(),
+                    });
+                }
+            }
+            Some(t @ "gathering") | Some(t @ "blog") => {
+                let key = match msg::key(&v) {
+                    Some(k) => k,
+                    None => continue,
+                };
+                let mut record = Record {
+                    kind: if t == "blog" { "blog" } else { "gathering" },
+                    author: author.to_string(),
+                    state: Map::new(),
+                    tombstone: None,
+                    updated: time,
+                };
+                apply(&mut record, content);
+                records.insert(key.to_string(), record);
+            }
+            _ => {}
+        }
+    }
+    progress.finish(&format!(
+        "Found {} records",
+        units::count(records.len() as u64)
+    ))?;
+
+    updates.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+    for u in &updates {
+        // A feed's own abouts make up its profile.
+        if u.target.starts_with('@') && u.target == u.author {
+            records.entry(u.target.clone()).or_insert_with(|| Record {
+                kind: "profile",
+                author: u.author.clone(),
+                state: Map::new(),
+                tombstone: None,
+                updated: u.time,
+            });
+        }
+        if let Some(r) = records.get_mut(&u.target) {
+            if r.author == u.author {
+                apply(r, &u.content);
+                r.updated = r.updated.max(u.time);
+            }
+        }
+    }
+
+    let mut keys: Vec<&String> = records
+        .iter()
+        .filter(|(_, r)| kinds.contains(&r.kind))
+        .map(|(k, _)| k)
+        .collect();
+    keys.sort();
+
+    let stdout = stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let (mut live, mut deleted) = (0, 0);
+    for k in keys {
+        let r = &records[k];
+        let mut line = json!({
+            "key": k,
+            "type": r.kind,
+            "author": r.author,
+            "updated": r.updated,
+            "state": r.state,
+        });
+        match &r.tombstone {
+            Some(t) => {
+                deleted += 1;
+                if !tombstoned {
+                    continue;
+                }
+                line["tombstone"] = t.clone();
+            }
+            None => live += 1,
+        }
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    eprintln!("{} records, {} tombstoned.", live + deleted, deleted);
+    Ok(())
+}
+
+fn apply(r: &mut Record, content: &Value) {
+    let fields = match content.as_object() {
+        Some(f) => f,
+        None => return,
+    };
+    for (k, v) in fields {
+        match k.as_str() {
+            "type" | "about" => {}
+            "tombstone" if v.is_null() => r.tombstone = None,
+            "tombstone" => r.tombstone = Some(v.clone()),
+            _ => {
+                r.state.insert(k.clone(), v.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::FileExt;
+
+use flumedb::flume_log::{Error, FlumeLog};
+use flumedb::offset_log::OffsetLog;
+use serde_json::{Deserializer, Value};
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::signature;
+use crate::units;
+
+// Every flume entry written by ssb-db starts like this.
+const ENVELOPE_START: &[u8] = b"{\"key\":\"%";
+
+// Recover what we can from a log whose framing is too damaged to walk, by
+// scanning the raw bytes for flume envelopes and parsing whatever JSON starts
+// there. Recovered messages are copied byte for byte into `out_log`, if their
+// signature (and key) verify, or regardless with `keep_unverified`. With
+// `tolerate_io_errors`, unreadable parts of the file are skipped instead of
+// failing the whole salvage.
+pub fn salvage(
+    path: &str,
+    mut out_log: OffsetLog<u32>,
+    keep_unverified: bool,
+    tolerate_io_errors: bool,
+) -> Result<(), Error> {
+    let data = if tolerate_io_errors {
+        let (data, skipped) = read_around_errors(path)?;
+        if !skipped.is_empty() {
+            eprintln!(
+                "Skipped {} unreadable regions (zero-filled):",
+                skipped.len()
+            );
+            for (start, end) in &skipped {
+                eprintln!("  {}..{} ({})", start, end, units::bytes(end - start));
+            }
+        }
+        data
+    } else {
+        fs::read(path)?
+    };
+    let mut progress = Progress::new(data.len() as u64);
+    let mut keys = HashSet::new();
+    let mut verified: u64 = 0;
+    let mut unverified: BTreeMap<String, u64> = BTreeMap::new();
+    let mut duplicates: u64 = 0;
+    let mut recovered_bytes: u64 = 0;
+
+    let mut pos = 0;
+    while let Some(found) = find(&data[pos..], ENVELOPE_START) {
+        let start = pos + found;
+        progress.update(start as u64, || {
+            format!("Recovered {} messages", units::count(keys.len() as u64))
+        })?;
+
+        let mut values = Deserializer::from_slice(&data[start..]).into_iter::<Value>();
+        let v = match values.next() {
+            Some(Ok(v)) if msg::key(&v).is_some() && msg::author(&v).is_some() => v,
+            _ => {
+                pos = start + 1;
+                continue;
+            }
+        };
+        let end = start + values.byte_offset();
+        pos = end;
+
+        if !keys.insert(msg::key(&v).unwrap().to_string()) {
+            duplicates += 1;
+            continue;
+        }
+        match signature::verify(&v) {
+            Ok(()) => verified += 1,
+            Err(why) => {
+                *unverified.entry(why.to_string()).or_default() += 1;
+                if !keep_unverified {
+                    continue;
+                }
+            }
+        }
+        out_log.append(&data[start..end])?;
+        recovered_bytes += (end - start) as u64;
+    }
+    progress.finish(&format!(
+        "Recovered {} messages",
+        units::count(keys.len() as u64)
+    ))?;
+
+    println!(
+        "Recovered {} messages with valid signatures ({} written, {} duplicates skipped).",
+        units::count(verified),
+        units::bytes(recovered_bytes),
+        units::count(duplicates)
+    );
+    if !unverified.is_empty() {
+        println!(
+            "Messages that failed verification ({}):",
+            if keep_unverified { "kept" } else { "left out" }
+        );
+        for (why, count) in &unverified {
+            println!("  {:>8}  {}", count, why);
+        }
+    }
+    Ok(())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+const CHUNK_SIZE: usize = 1 << 20;
+const BLOCK_SIZE: usize = 4096;
+
+// Unreadable byte ranges, as (start, end).
+type Skipped = Vec<(u64, u64)>;
+
+// Read the whole file, even from a failing disk: a chunk that can't be read is
+// retried block by block, and blocks that still fail are left zero-filled.
+// Returns the data and the unreadable byte ranges.
+fn read_around_errors(path: &str) -> io::Result<(Vec<u8>, Skipped)> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+    let mut data = vec![0; len];
+    let mut skipped = Skipped::new();
+    let mut progress = Progress::new(len as u64);
+
+    for chunk_start in (0..len).step_by(CHUNK_SIZE) {
+        let chunk_end = (chunk_start + CHUNK_SIZE).min(len);
+        if read_fully_at(&file, &mut data[chunk_start..chunk_end], chunk_start).is_err() {
+            for start in (chunk_start..chunk_end).step_by(BLOCK_SIZE) {
+                let end = (start + BLOCK_SIZE).min(chunk_end);
+                if read_fully_at(&file, &mut data[start..end], start).is_ok() {
+                    continue;
+                }
+                for b in &mut data[start..end] {
+                    *b = 0;
+                }
+                match skipped.last_mut() {
+                    Some(last) if last.1 == start as u64 => last.1 = end as u64,
+                    _ => skipped.push((start as u64, end as u64)),
+                }
+            }
+        }
+        progress.update(chunk_end as u64, || {
+            format!("Read {}", units::bytes(chunk_end as u64))
+        })?;
+    }
+    progress.finish(&format!("Read {}", units::bytes(len as u64)))?;
+    Ok((data, skipped))
+}
+
+fn read_fully_at(file: &File, buf: &mut [u8], offset: usize) -> io::Result<()> {
+    let mut done = 0;
+    while done < buf.len() {
+        match file.read_at(&mut buf[done..], (offset + done) as u64) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => done += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::collections::{HashMap, VecDeque};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+// A small deterministic PRNG (splitmix64), so the same seed gives the same
+// order on every machine and with every version of feedrick.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in 0..n.
+    fn below(&mut self, n: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+}
+
+// The offsets of the entries of `log` in a random order, seeded by `seed`,
+// that keeps each feed's messages in sequence order. Every interleaving of
+// the feeds is equally likely, so the order says nothing about when or from
+// whom messages were received. Entries that aren't messages are placed like
+// one-message feeds.
+pub fn order(log: &OffsetLog<u32>, seed: u64) -> Result<Vec<u64>, Error> {
+    let mut progress = Progress::new(log.end());
+    let mut feeds: HashMap<String, Vec<(u64, u64)>> = HashMap::new();
+    let mut count: u64 = 0;
+    for e in log.iter() {
+        let v = msg::parse(&e);
+        let feed = match v.as_ref().and_then(msg::author) {
+            Some(a) => a.to_string(),
+            None => format!("#{}", e.offset),
+        };
+        let seq = v.as_ref().and_then(msg::sequence).unwrap_or(0);
+        feeds.entry(feed).or_default().push((seq, e.offset));
+        count += 1;
+        progress.update(e.offset, || format!("Read {} entries", units::count(count)))?;
+    }
+    progress.finish(&format!("Read {} entries", units::count(count)))?;
+
+    // Sorted, so the result doesn't depend on HashMap order.
+    let mut feeds: Vec<(String, Vec<(u64, u64)>)> = feeds.into_iter().collect();
+    feeds.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    // One slot per message, naming its feed; shuffling the slots and then
+    // filling each with its feed's next message gives a uniform interleaving.
+    let mut slots = Vec::with_capacity(count as usize);
+    let mut queues = Vec::with_capacity(feeds.len());
+    for (i, (_, mut entries)) in feeds.into_iter().enumerate() {
+        entries.sort_unstable();
+        slots.extend(std::iter::repeat_n(i, entries.len()));
+        queues.push(
+            entries
+                .into_iter()
+                .map(|(_, o)| o)
+                .collect::<VecDeque<u64>>(),
+        );
+    }
+    let mut rng = SplitMix64(seed);
+    for i in (1..slots.len()).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        slots.swap(i, j);
+    }
+    Ok(slots
+        .into_iter()
+        .map(|f| queues[f].pop_front().unwrap())
+        .collect())
+}
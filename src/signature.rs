@@ -0,0 +1,81 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use serde_json::ser::PrettyFormatter;
+use serde_json::{Serializer, Value};
+use sha2::{Digest, Sha256};
+
+// Why a message failed verification.
+pub enum Invalid {
+    Malformed(&'static str),
+    BadSignature,
+    KeyMismatch,
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Invalid::Malformed(why) => write!(f, "malformed message: {}", why),
+            Invalid::BadSignature => write!(f, "signature doesn't verify"),
+            Invalid::KeyMismatch => write!(f, "key isn't the hash of the message"),
+        }
+    }
+}
+
+// Check the signature of the message in a flume envelope (`{ key, value }`),
+// and that its key is the message's hash. The value must have been parsed
+// with its fields in their original order, since ssb signs the message as
+// formatted by `JSON.stringify(value, null, 2)`.
+pub fn verify(envelope: &Value) -> Result<(), Invalid> {
+    let value = envelope
+        .get("value")
+        .and_then(|v| v.as_object())
+        .ok_or(Invalid::Malformed("no value"))?;
+    let author = value
+        .get("author")
+        .and_then(|a| a.as_str())
+        .ok_or(Invalid::Malformed("no author"))?;
+    let signature = value
+        .get("signature")
+        .and_then(|s| s.as_str())
+        .ok_or(Invalid::Malformed("no signature"))?;
+
+    let public_key = author
+        .strip_prefix('@')
+        .and_then(|a| a.strip_suffix(".ed25519"))
+        .and_then(|a| base64::decode(a).ok())
+        .and_then(|k| PublicKey::from_bytes(&k).ok())
+        .ok_or(Invalid::Malformed("author isn't an ed25519 feed id"))?;
+    let signature = signature
+        .strip_suffix(".sig.ed25519")
+        .and_then(|s| base64::decode(s).ok())
+        .and_then(|s| Signature::try_from(&s[..]).ok())
+        .ok_or(Invalid::Malformed("signature isn't an ed25519 signature"))?;
+
+    let mut unsigned = value.clone();
+    unsigned.remove("signature");
+    public_key
+        .verify(&to_js_string(&Value::Object(unsigned)), &signature)
+        .map_err(|_| Invalid::BadSignature)?;
+
+    if let Some(key) = envelope.get("key").and_then(|k| k.as_str()) {
+        // Legacy ssb hashes the JSON string as "binary" (latin-1): each UTF-16
+        // code unit truncated to a byte.
+        let json = String::from_utf8(to_js_string(&Value::Object(value.clone()))).unwrap();
+        let bytes: Vec<u8> = json.encode_utf16().map(|u| u as u8).collect();
+        let expected = format!("%{}.sha256", base64::encode(&Sha256::digest(&bytes)));
+        if key != expected {
+            return Err(Invalid::KeyMismatch);
+        }
+    }
+    Ok(())
+}
+
+// `JSON.stringify(v, null, 2)`
+fn to_js_string(v: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut ser = Serializer::with_formatter(&mut out, PrettyFormatter::with_indent(b"  "));
+    serde::Serialize::serialize(v, &mut ser).unwrap();
+    out
+}
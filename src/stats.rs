@@ -0,0 +1,645 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flumedb::flume_log::Error;
+use flumedb::log_entry::LogEntry;
+use flumedb::offset_log::OffsetLog;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use crate::apps::Analyzer;
+use crate::msg;
+use crate::names::{self, Names};
+use crate::progress::Progress;
+use crate::render;
+use crate::report::{self, Format};
+use crate::units;
+use crate::uri;
+
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+// How much of the log is read before its entries are handed to the threads.
+const BATCH_BYTES: usize = 16 << 20;
+
+// Activity buckets, by age of a feed's latest message.
+const BUCKETS: &[(&str, f64)] = &[
+    ("active this week", 7.0 * DAY_MS),
+    ("active this month", 30.0 * DAY_MS),
+    ("active this year", 365.0 * DAY_MS),
+    ("dormant > 1y", f64::INFINITY),
+];
+
+#[derive(Default)]
+pub struct FeedStats {
+    pub messages: u64,
+    pub bytes: u64,
+    pub first_asserted: Option<f64>,
+    pub last_asserted: f64,
+    pub last_received: f64,
+    pub first_sequence: Option<u64>,
+    pub latest_sequence: u64,
+    // content type ("<private>" for encrypted content) -> messages
+    pub types: HashMap<String, u64>,
+    // content type -> bytes of those messages
+    pub type_bytes: HashMap<String, u64>,
+}
+
+impl FeedStats {
+    fn add(&mut self, v: &Value, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+        if let Some(seq) = msg::sequence(v) {
+            self.first_sequence = Some(self.first_sequence.map_or(seq, |f| f.min(seq)));
+        }
+        self.latest_sequence = self.latest_sequence.max(msg::sequence(v).unwrap_or(0));
+        let typ = if msg::is_private(v) {
+            "<private>"
+        } else {
+            msg::content_type(v).unwrap_or("<none>")
+        };
+        *self.types.entry(typ.to_string()).or_default() += 1;
+        *self.type_bytes.entry(typ.to_string()).or_default() += bytes as u64;
+        if let Some(t) = msg::asserted_timestamp(v) {
+            self.first_asserted = Some(self.first_asserted.map_or(t, |f| f.min(t)));
+            self.last_asserted = self.last_asserted.max(t);
+        }
+        if let Some(t) = msg::received_timestamp(v) {
+            self.last_received = self.last_received.max(t);
+        }
+    }
+
+    fn merge(&mut self, other: FeedStats) {
+        self.messages += other.messages;
+        self.bytes += other.bytes;
+        if let Some(t) = other.first_asserted {
+            self.first_asserted = Some(self.first_asserted.map_or(t, |f| f.min(t)));
+        }
+        self.last_asserted = self.last_asserted.max(other.last_asserted);
+        self.last_received = self.last_received.max(other.last_received);
+        if let Some(seq) = other.first_sequence {
+            self.first_sequence = Some(self.first_sequence.map_or(seq, |f| f.min(seq)));
+        }
+        self.latest_sequence = self.latest_sequence.max(other.latest_sequence);
+        for (t, n) in other.types {
+            *self.types.entry(t).or_default() += n;
+        }
+        for (t, n) in other.type_bytes {
+            *self.type_bytes.entry(t).or_default() += n;
+        }
+    }
+
+    // When the feed's latest message was received, or asserted.
+    fn latest(&self, received: bool) -> f64 {
+        if received {
+            self.last_received
+        } else {
+            self.last_asserted
+        }
+    }
+}
+
+// A single pass over a log: every entry, and per-feed stats for the ones that
+// are messages.
+#[derive(Default)]
+pub struct Scan {
+    pub entries: u64,
+    // Entries that aren't messages: deleted, or not parseable.
+    pub unreadable: u64,
+    // Messages by feeds on the skip list.
+    pub skipped: u64,
+    pub feeds: HashMap<String, FeedStats>,
+}
+
+impl Scan {
+    fn add(&mut self, e: &LogEntry, skip: &HashSet<String>) {
+        self.entries += 1;
+        match msg::parse(e).filter(|v| msg::author(v).is_some()) {
+            Some(ref v) if skip.contains(msg::author(v).unwrap()) => self.skipped += 1,
+            Some(v) => self
+                .feeds
+                .entry(msg::author(&v).unwrap().to_string())
+                .or_default()
+                .add(&v, e.data.len()),
+            None => self.unreadable += 1,
+        }
+    }
+
+    fn merge(mut self, other: Scan) -> Scan {
+        self.entries += other.entries;
+        self.unreadable += other.unreadable;
+        self.skipped += other.skipped;
+        for (id, stats) in other.feeds {
+            self.feeds.entry(id).or_default().merge(stats);
+        }
+        self
+    }
+}
+
+// The log is read in batches, each parsed and tallied on all cores into
+// per-thread scans that are merged at the end. Every message is also passed
+// to `analyzers`, in log order on this thread, which parses it again; only
+// `stats --apps` has any. Messages by feeds in `skip` are only counted.
+pub fn scan_feeds(
+    log: &OffsetLog<u32>,
+    analyzers: &mut [Box<dyn Analyzer>],
+    skip: &HashSet<String>,
+) -> Result<Scan, Error> {
+    let mut progress = Progress::new(log.end());
+    let mut scan = Scan::default();
+
+    let mut entries = log.iter();
+    loop {
+        let mut batch: Vec<LogEntry> = Vec::new();
+        let mut size = 0;
+        for e in entries.by_ref() {
+            size += e.data.len();
+            batch.push(e);
+            if size >= BATCH_BYTES {
+                break;
+            }
+        }
+        let last = match batch.last() {
+            Some(e) => e.offset,
+            None => break,
+        };
+        let part = batch
+            .par_iter()
+            .fold(Scan::default, |mut s, e| {
+                s.add(e, skip);
+                s
+            })
+            .reduce(Scan::default, Scan::merge);
+        scan = scan.merge(part);
+        if !analyzers.is_empty() {
+            let messages = batch
+                .iter()
+                .filter_map(msg::parse)
+                .filter(|v| msg::author(v).is_some_and(|a| !skip.contains(a)));
+            for v in messages {
+                for a in analyzers.iter_mut() {
+                    a.add(&v);
+                }
+            }
+        }
+        progress.update(last, || {
+            format!("Scanned {} feeds", units::count(scan.feeds.len() as u64))
+        })?;
+    }
+    progress.finish(&format!(
+        "Scanned {} feeds",
+        units::count(scan.feeds.len() as u64)
+    ))?;
+    if scan.skipped > 0 {
+        eprintln!(
+            "Left out {} messages by feeds on the skip list (see --no-skip-list).",
+            units::count(scan.skipped)
+        );
+    }
+    Ok(scan)
+}
+
+// Totals for the whole log: entries, authors, bytes, messages per content
+// type, the range of asserted timestamps, and the `top` authors by messages
+// (named from `names`, if given).
+pub fn summary_report(scan: &Scan, top: usize, names: Option<&Names>) -> Result<(), Error> {
+    let feeds = &scan.feeds;
+    let messages: u64 = feeds.values().map(|s| s.messages).sum();
+    let bytes: u64 = feeds.values().map(|s| s.bytes).sum();
+    println!(
+        "{} entries ({} unreadable or deleted), {} messages by {} authors, {}.",
+        units::count(scan.entries),
+        units::count(scan.unreadable),
+        units::count(messages),
+        units::count(feeds.len() as u64),
+        units::bytes(bytes)
+    );
+    if let Some((first, last)) = asserted_range(feeds) {
+        println!(
+            "Asserted timestamps from {} to {}.",
+            render::format_date(first),
+            render::format_date(last)
+        );
+    }
+
+    println!();
+    println!("Messages by content type:");
+    let mut types: Vec<_> = type_totals(feeds).into_iter().collect();
+    types.sort_by_key(|(t, n)| (std::cmp::Reverse(*n), *t));
+    for (t, n) in types {
+        println!("  {:<22} {:>10}", t, units::count(n));
+    }
+
+    let authors = by_messages(feeds);
+    println!();
+    println!(
+        "Messages per author (top {} of {}):",
+        top.min(authors.len()),
+        authors.len()
+    );
+    for (id, s) in authors.iter().take(top) {
+        println!(
+            "  {:>10} {:>10}  {}",
+            units::count(s.messages),
+            units::bytes(s.bytes),
+            names::label(names, id)
+        );
+    }
+    println!();
+    Ok(())
+}
+
+// Bucket feeds by how long ago their latest message was asserted (or
+// received), so it's clear how much of a log is dormant.
+pub fn activity_report(
+    feeds: &HashMap<String, FeedStats>,
+    received: bool,
+    list: bool,
+) -> Result<(), Error> {
+    let now = now_ms();
+    let last = |s: &FeedStats| s.latest(received);
+    let counts = activity(feeds, received, now);
+
+    println!(
+        "Feed activity by latest {} timestamp:",
+        if received { "received" } else { "asserted" }
+    );
+    for ((name, _), (feeds, messages, bytes)) in BUCKETS.iter().zip(counts) {
+        println!(
+            "  {:<18} {:>8} feeds {:>10} messages {:>10}",
+            name,
+            units::count(feeds),
+            units::count(messages),
+            units::bytes(bytes)
+        );
+    }
+
+    if list {
+        println!();
+        for (id, s) in by_latest(feeds, received) {
+            println!(
+                "{:>8.1} days ago  {:>8} messages  {}",
+                (now - last(s)) / DAY_MS,
+                units::count(s.messages),
+                uri::show(id)
+            );
+        }
+    }
+    Ok(())
+}
+
+// The summary and activity reports as data, for `--json` and `--template`:
+// totals, `first_asserted` and `last_asserted` (ms), `types`, the `top`
+// authors, the `activity` buckets, and with `list` every feed's latest time.
+pub fn emit_report(
+    scan: &Scan,
+    top: usize,
+    names: Option<&Names>,
+    received: bool,
+    list: bool,
+    format: &Format,
+) -> Result<(), Error> {
+    let feeds = &scan.feeds;
+    let (first, last) = match asserted_range(feeds) {
+        Some((first, last)) => (Some(first), Some(last)),
+        None => (None, None),
+    };
+    let author = |id: &String, s: &FeedStats| {
+        json!({
+            "id": id,
+            "name": names.and_then(|n| n.name(id)),
+            "messages": s.messages,
+            "bytes": s.bytes,
+        })
+    };
+    let top_authors: Vec<Value> = by_messages(feeds)
+        .into_iter()
+        .take(top)
+        .map(|(id, s)| author(id, s))
+        .collect();
+    let activity: Vec<Value> = BUCKETS
+        .iter()
+        .zip(activity(feeds, received, now_ms()))
+        .map(|((name, _), (feeds, messages, bytes))| {
+            json!({ "bucket": name, "feeds": feeds, "messages": messages, "bytes": bytes })
+        })
+        .collect();
+    let mut data = json!({
+        "entries": scan.entries,
+        "unreadable": scan.unreadable,
+        "skipped": scan.skipped,
+        "messages": feeds.values().map(|s| s.messages).sum::<u64>(),
+        "authors": feeds.len(),
+        "bytes": feeds.values().map(|s| s.bytes).sum::<u64>(),
+        "first_asserted": first,
+        "last_asserted": last,
+        "types": type_totals(feeds),
+        "top_authors": top_authors,
+        "activity": activity,
+    });
+    if list {
+        data["feeds"] = by_latest(feeds, received)
+            .into_iter()
+            .map(
+                |(id, s)| json!({ "id": id, "messages": s.messages, "latest": s.latest(received) }),
+            )
+            .collect();
+    }
+    report::emit(&data, format)
+}
+
+// The earliest and latest asserted timestamps of any message, if any has one.
+fn asserted_range(feeds: &HashMap<String, FeedStats>) -> Option<(f64, f64)> {
+    let first = feeds
+        .values()
+        .filter_map(|s| s.first_asserted)
+        .fold(None, |a: Option<f64>, t| Some(a.map_or(t, |a| a.min(t))))?;
+    let last = feeds
+        .values()
+        .map(|s| s.last_asserted)
+        .fold(first, f64::max);
+    Some((first, last))
+}
+
+// Feeds by messages, most first.
+fn by_messages(feeds: &HashMap<String, FeedStats>) -> Vec<(&String, &FeedStats)> {
+    let mut authors: Vec<_> = feeds.iter().collect();
+    authors.sort_by_key(|(id, s)| (std::cmp::Reverse(s.messages), *id));
+    authors
+}
+
+// Feeds by their latest message, most recent first.
+fn by_latest(feeds: &HashMap<String, FeedStats>, received: bool) -> Vec<(&String, &FeedStats)> {
+    let mut feeds: Vec<_> = feeds.iter().collect();
+    feeds.sort_by(|(_, a), (_, b)| b.latest(received).partial_cmp(&a.latest(received)).unwrap());
+    feeds
+}
+
+// Feeds, messages and bytes in each of the BUCKETS.
+fn activity(feeds: &HashMap<String, FeedStats>, received: bool, now: f64) -> Vec<(u64, u64, u64)> {
+    let mut counts = vec![(0u64, 0u64, 0u64); BUCKETS.len()];
+    for s in feeds.values() {
+        let age = now - s.latest(received);
+        let i = BUCKETS.iter().position(|(_, max)| age < *max).unwrap();
+        counts[i].0 += 1;
+        counts[i].1 += s.messages;
+        counts[i].2 += s.bytes;
+    }
+    counts
+}
+
+fn now_ms() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as f64)
+        .unwrap_or(0.0)
+}
+
+// Compare two logs (eg. before and after a prune or migration): totals,
+// messages per content type and per author, and feeds whose latest sequence
+// number differs. Only the `top` largest author differences are listed.
+pub fn compare_report(
+    before: &HashMap<String, FeedStats>,
+    after: &HashMap<String, FeedStats>,
+    top: usize,
+) -> Result<(), Error> {
+    let totals = |feeds: &HashMap<String, FeedStats>| {
+        feeds
+            .values()
+            .fold((0, 0), |(m, b), s| (m + s.messages, b + s.bytes))
+    };
+    let (before_messages, before_bytes) = totals(before);
+    let (after_messages, after_bytes) = totals(after);
+    println!(
+        "{:<22} {:>14} {:>14} {:>14}",
+        "", "before", "after", "change"
+    );
+    print_row("feeds", before.len() as u64, after.len() as u64);
+    print_row("messages", before_messages, after_messages);
+    print_row("bytes", before_bytes, after_bytes);
+
+    let (before_types, after_types) = (type_totals(before), type_totals(after));
+    let mut types: Vec<&str> = before_types
+        .keys()
+        .chain(after_types.keys())
+        .cloned()
+        .collect();
+    types.sort_unstable();
+    types.dedup();
+    println!();
+    println!("Messages by content type:");
+    for t in types {
+        let (b, a) = (
+            before_types.get(t).cloned().unwrap_or(0),
+            after_types.get(t).cloned().unwrap_or(0),
+        );
+        print_row(&format!("  {}", t), b, a);
+    }
+
+    let mut ids: Vec<&String> = before.keys().chain(after.keys()).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let count =
+        |feeds: &HashMap<String, FeedStats>, id: &str| feeds.get(id).map_or(0, |s| s.messages);
+    let seq = |feeds: &HashMap<String, FeedStats>, id: &str| {
+        feeds.get(id).map_or(0, |s| s.latest_sequence)
+    };
+
+    let only_before = ids.iter().filter(|id| !after.contains_key(**id)).count();
+    let only_after = ids.iter().filter(|id| !before.contains_key(**id)).count();
+    println!();
+    println!(
+        "{} feeds only before, {} only after, {} in both.",
+        units::count(only_before as u64),
+        units::count(only_after as u64),
+        units::count((ids.len() - only_before - only_after) as u64)
+    );
+
+    let mut changed: Vec<(&String, u64, u64)> = ids
+        .iter()
+        .map(|id| (*id, count(before, id), count(after, id)))
+        .filter(|(_, b, a)| b != a)
+        .collect();
+    changed.sort_by_key(|(id, b, a)| (std::cmp::Reverse((*a as i64 - *b as i64).abs()), *id));
+    if !changed.is_empty() {
+        println!();
+        println!(
+            "Largest changes in messages per author ({} authors changed):",
+            units::count(changed.len() as u64)
+        );
+        for (id, b, a) in changed.iter().take(top) {
+            // ids are too long for the name column
+            println!(
+                "  {:>8} {:>8} {:>8}  {}",
+                units::count(*b),
+                units::count(*a),
+                change(*b, *a),
+                uri::show(id)
+            );
+        }
+    }
+
+    let (mut ahead, mut behind) = (0u64, 0u64);
+    for id in &ids {
+        match seq(after, id).cmp(&seq(before, id)) {
+            std::cmp::Ordering::Greater => ahead += 1,
+            std::cmp::Ordering::Less => behind += 1,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    println!();
+    println!(
+        "Frontier: {} feeds have a later latest message after, {} an earlier one (or none).",
+        units::count(ahead),
+        units::count(behind)
+    );
+    Ok(())
+}
+
+fn type_totals(feeds: &HashMap<String, FeedStats>) -> BTreeMap<&str, u64> {
+    let mut types = BTreeMap::new();
+    for s in feeds.values() {
+        for (t, n) in &s.types {
+            *types.entry(t.as_str()).or_default() += n;
+        }
+    }
+    types
+}
+
+fn print_row(name: &str, before: u64, after: u64) {
+    println!(
+        "{:<22} {:>14} {:>14} {:>14}",
+        name,
+        units::count(before),
+        units::count(after),
+        change(before, after)
+    );
+}
+
+// `after - before`, signed.
+fn change(before: u64, after: u64) -> String {
+    if after >= before {
+        format!("+{}", units::count(after - before))
+    } else {
+        format!("-{}", units::count(before - after))
+    }
+}
+
+// Every author in the log: messages, the lowest and highest sequence
+// numbers seen, and the latest asserted time. Sorted by messages, most
+// first, or with `by_name` by feed id. With `names`, each feed's name
+// follows its id.
+pub fn authors_report(
+    feeds: &HashMap<String, FeedStats>,
+    by_name: bool,
+    names: Option<&Names>,
+    format: &Format,
+) -> Result<(), Error> {
+    let mut authors: Vec<(&String, &FeedStats)> = feeds.iter().collect();
+    if by_name {
+        authors.sort_by(|a, b| a.0.cmp(b.0));
+    } else {
+        authors.sort_by(|a, b| b.1.messages.cmp(&a.1.messages).then(a.0.cmp(b.0)));
+    }
+    if !matches!(format, Format::Text) {
+        let authors: Vec<Value> = authors
+            .iter()
+            .map(|(id, s)| {
+                json!({
+                    "id": id,
+                    "name": names.and_then(|n| n.name(id)),
+                    "messages": s.messages,
+                    "first_sequence": s.first_sequence,
+                    "latest_sequence": s.latest_sequence,
+                    "latest": if s.last_asserted > 0.0 { Some(s.last_asserted) } else { None },
+                })
+            })
+            .collect();
+        return report::emit(&json!({ "authors": authors }), format);
+    }
+    println!(
+        "{:>10}  {:>17}  {:<20}  author",
+        "messages", "sequences", "latest"
+    );
+    for (author, s) in authors {
+        let seqs = format!("{}-{}", s.first_sequence.unwrap_or(0), s.latest_sequence);
+        let latest = if s.last_asserted > 0.0 {
+            render::format_date(s.last_asserted)
+        } else {
+            "-".to_string()
+        };
+        println!(
+            "{:>10}  {:>17}  {:<20}  {}",
+            units::count(s.messages),
+            seqs,
+            latest,
+            names::label(names, author)
+        );
+    }
+    Ok(())
+}
+
+// Messages and bytes per content type across the whole log, largest first
+// (by bytes, or with `by_count` by messages), with each type's share of the
+// bytes.
+pub fn types_report(scan: &Scan, by_count: bool, format: &Format) -> Result<(), Error> {
+    let mut types: HashMap<&str, (u64, u64)> = HashMap::new();
+    for s in scan.feeds.values() {
+        for (t, n) in &s.types {
+            let entry = types.entry(t.as_str()).or_default();
+            entry.0 += n;
+            entry.1 += s.type_bytes.get(t).cloned().unwrap_or(0);
+        }
+    }
+    let total_bytes: u64 = types.values().map(|(_, b)| b).sum();
+    let total_messages: u64 = types.values().map(|(m, _)| m).sum();
+
+    let mut types: Vec<_> = types.into_iter().collect();
+    if by_count {
+        types.sort_by_key(|(t, (m, _))| (std::cmp::Reverse(*m), *t));
+    } else {
+        types.sort_by_key(|(t, (_, b))| (std::cmp::Reverse(*b), *t));
+    }
+    if !matches!(format, Format::Text) {
+        let list: Vec<Value> = types
+            .iter()
+            .map(
+                |(t, (messages, bytes))| json!({ "type": t, "messages": messages, "bytes": bytes }),
+            )
+            .collect();
+        return report::emit(
+            &json!({
+                "types": list,
+                "messages": total_messages,
+                "bytes": total_bytes,
+                "unreadable": scan.unreadable,
+            }),
+            format,
+        );
+    }
+    println!("{:>12}  {:>10}  {:>6}  type", "messages", "bytes", "share");
+    for (t, (messages, bytes)) in &types {
+        println!(
+            "{:>12}  {:>10}  {:>5.1}%  {}",
+            units::count(*messages),
+            units::bytes(*bytes),
+            100.0 * *bytes as f64 / total_bytes.max(1) as f64,
+            t
+        );
+    }
+    println!(
+        "{} types, {} messages, {} ({} entries unreadable or deleted).",
+        units::count(types.len() as u64),
+        units::count(total_messages),
+        units::bytes(total_bytes),
+        units::count(scan.unreadable)
+    );
+    Ok(())
+}
+
+pub fn apps_report(analyzers: &[Box<dyn Analyzer>]) {
+    for a in analyzers {
+        println!();
+        println!("{}:", a.name());
+        a.report();
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::{BTreeMap, HashSet};
+
+use flumedb::flume_log::{Error, FlumeLog};
+use flumedb::offset_log::OffsetLog;
+
+use crate::frontier::{self, Frontier};
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+// Append to `into` the messages of `from` it doesn't have (by key), keeping
+// each feed in sequence order: only messages newer than the feed's latest in
+// `into` are appended, lowest sequence first. Older missing messages can't be
+// appended without breaking that order, so they're counted and left out.
+pub fn sync(from: &OffsetLog<u32>, into: &mut OffsetLog<u32>, dry_run: bool) -> Result<(), Error> {
+    let mut keys = HashSet::new();
+    let mut latest = Frontier::new();
+    let mut progress = Progress::new(into.end());
+    for e in into.iter() {
+        progress.update(e.offset, || {
+            format!("Indexed {} messages", units::count(keys.len() as u64))
+        })?;
+        if let Some(v) = msg::parse(&e) {
+            if let Some(key) = msg::key(&v) {
+                keys.insert(key.to_string());
+            }
+            frontier::update(&mut latest, &v);
+        }
+    }
+    progress.finish(&format!(
+        "Indexed {} messages",
+        units::count(keys.len() as u64)
+    ))?;
+
+    // (offset in `from`, sequence) of each message to append, by feed
+    let mut missing: BTreeMap<String, Vec<(u64, u64)>> = BTreeMap::new();
+    let mut order: Vec<String> = Vec::new();
+    let mut too_old: u64 = 0;
+    let mut progress = Progress::new(from.end());
+    for e in from.iter() {
+        progress.update(e.offset, || {
+            format!(
+                "Found {} missing messages",
+                units::count(order.len() as u64)
+            )
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let (key, author, seq) = match (msg::key(&v), msg::author(&v), msg::sequence(&v)) {
+            (Some(k), Some(a), Some(s)) => (k, a, s),
+            _ => continue,
+        };
+        if !keys.insert(key.to_string()) {
+            continue;
+        }
+        if latest.get(author).is_some_and(|l| seq <= *l) {
+            too_old += 1;
+            continue;
+        }
+        missing
+            .entry(author.to_string())
+            .or_default()
+            .push((e.offset, seq));
+        order.push(author.to_string());
+    }
+    progress.finish(&format!(
+        "Found {} missing messages",
+        units::count(order.len() as u64)
+    ))?;
+
+    // Keep the interleaving of feeds from `from`, but give each feed's slots
+    // to its messages in sequence order.
+    for msgs in missing.values_mut() {
+        msgs.sort_by_key(|(_, seq)| std::cmp::Reverse(*seq));
+    }
+    if !dry_run {
+        let mut progress = Progress::new(order.len() as u64);
+        for (i, author) in order.iter().enumerate() {
+            let (offset, _) = missing.get_mut(author).unwrap().pop().unwrap();
+            into.append(&from.get(offset)?)?;
+            progress.update(i as u64, || {
+                format!("Appended {} messages", units::count(i as u64))
+            })?;
+        }
+        progress.finish(&format!(
+            "Appended {} messages",
+            units::count(order.len() as u64)
+        ))?;
+    }
+
+    println!(
+        "{} {} messages from {} feeds.",
+        if dry_run { "Would append" } else { "Appended" },
+        units::count(order.len() as u64),
+        units::count(missing.len() as u64)
+    );
+    if too_old > 0 {
+        println!(
+            "Left out {} missing messages older than their feed's latest in the destination; use merge to rebuild a log with them.",
+            units::count(too_old)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, stdin, stdout, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::Value;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::{clear, cursor, style};
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::render;
+use crate::units;
+
+// Which conversations to show: those a feed posted in, or those with a post
+// in a channel.
+pub enum Scope {
+    Feed(String),
+    Channel(String),
+}
+
+impl Scope {
+    fn matches(&self, v: &Value) -> bool {
+        match self {
+            Scope::Feed(id) => msg::author(v) == Some(id.as_str()),
+            Scope::Channel(name) => msg::in_channel(v, name),
+        }
+    }
+}
+
+// A root post and its replies, oldest first. The root is missing when the log
+// only has replies to it.
+pub struct Thread {
+    pub key: String,
+    pub root: Option<Value>,
+    pub replies: Vec<Value>,
+}
+
+impl Thread {
+    fn latest(&self) -> f64 {
+        self.root
+            .iter()
+            .chain(&self.replies)
+            .filter_map(msg::asserted_timestamp)
+            .fold(0.0, f64::max)
+    }
+}
+
+// The thread a post belongs to: its `root`, or the post itself.
+fn thread_key(v: &Value) -> Option<&str> {
+    msg::content(v)
+        .and_then(|c| c.get("root"))
+        .and_then(|r| r.as_str())
+        .or_else(|| msg::key(v))
+}
+
+fn posts(log: &OffsetLog<u32>) -> impl Iterator<Item = Value> + '_ {
+    log.iter()
+        .filter_map(|e| msg::parse(&e))
+        .filter(|v| msg::content_type(v) == Some("post"))
+}
+
+// Group the posts in `log` into the threads `scope` selects, most recently
+// active first. The log is read twice: once to find the threads, once to
+// gather every post in them, whoever wrote it.
+pub fn collect(log: &OffsetLog<u32>, scope: &Scope) -> Result<Vec<Thread>, Error> {
+    let mut progress = Progress::new(2 * log.end());
+
+    let mut selected = HashSet::new();
+    for v in posts(log) {
+        if scope.matches(&v) {
+            if let Some(k) = thread_key(&v) {
+                selected.insert(k.to_string());
+            }
+        }
+    }
+    progress.update(log.end(), || {
+        format!("Found {} threads", units::count(selected.len() as u64))
+    })?;
+
+    let mut threads: HashMap<String, Thread> = HashMap::new();
+    for v in posts(log) {
+        let k = match thread_key(&v) {
+            Some(k) if selected.contains(k) => k.to_string(),
+            _ => continue,
+        };
+        let is_root = msg::key(&v) == Some(k.as_str());
+        let thread = threads.entry(k.clone()).or_insert_with(|| Thread {
+            key: k,
+            root: None,
+            replies: Vec::new(),
+        });
+        if is_root {
+            thread.root = Some(v);
+        } else {
+            thread.replies.push(v);
+        }
+    }
+    progress.finish(&format!(
+        "Found {} threads",
+        units::count(selected.len() as u64)
+    ))?;
+
+    let mut threads: Vec<Thread> = threads.into_values().collect();
+    for t in &mut threads {
+        t.replies.sort_by(|a, b| {
+            let time = |v| msg::asserted_timestamp(v).unwrap_or(0.0);
+            time(a).partial_cmp(&time(b)).unwrap()
+        });
+    }
+    threads.sort_by(|a, b| b.latest().partial_cmp(&a.latest()).unwrap());
+    Ok(threads)
+}
+
+// A line on screen: a thread, or one of its replies when it's expanded.
+enum Row {
+    Thread(usize),
+    Reply(usize, usize),
+}
+
+// Browse `threads` in the terminal: each thread is a line that expands to
+// list its replies, and any post can be opened in full.
+pub fn view(threads: &[Thread], markdown: bool) -> Result<(), Error> {
+    if threads.is_empty() {
+        println!("No threads found.");
+        return Ok(());
+    }
+    let mut keys = stdin().keys();
+    let mut stdout = stdout().into_raw_mode()?;
+    let mut expanded = vec![false; threads.len()];
+    let mut selected = 0;
+    let mut top = 0;
+
+    draw_list(threads, &expanded, selected, &mut top, &mut stdout)?;
+    while let Some(c) = keys.next() {
+        let current = rows(threads, &expanded);
+        match c? {
+            Key::Char('q') | Key::Ctrl('c') | Key::Esc => break,
+            Key::Up | Key::Char('k') => selected = selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => selected = (selected + 1).min(current.len() - 1),
+            Key::Char(' ') | Key::Right | Key::Left | Key::Char('\t') => {
+                let t = match current[selected] {
+                    Row::Thread(t) | Row::Reply(t, _) => t,
+                };
+                expanded[t] = !expanded[t];
+                // Keep the cursor on the thread when collapsing from a reply.
+                selected = rows(threads, &expanded)
+                    .iter()
+                    .position(|r| matches!(r, Row::Thread(i) if *i == t))
+                    .unwrap();
+            }
+            Key::Char('\n') | Key::Char('o') => {
+                let post = match current[selected] {
+                    Row::Thread(t) => threads[t].root.as_ref(),
+                    Row::Reply(t, r) => threads[t].replies.get(r),
+                };
+                if let Some(v) = post {
+                    draw_post(v, markdown, &mut stdout)?;
+                    // Any key goes back to the list.
+                    if keys.next().is_none() {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+        draw_list(threads, &expanded, selected, &mut top, &mut stdout)?;
+    }
+    write!(stdout, "{}{}", clear::All, cursor::Goto(1, 1))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn rows(threads: &[Thread], expanded: &[bool]) -> Vec<Row> {
+    let mut rows = Vec::new();
+    for (t, thread) in threads.iter().enumerate() {
+        rows.push(Row::Thread(t));
+        if expanded[t] {
+            rows.extend((0..thread.replies.len()).map(|r| Row::Reply(t, r)));
+        }
+    }
+    rows
+}
+
+fn draw_list<W: Write>(
+    threads: &[Thread],
+    expanded: &[bool],
+    selected: usize,
+    top: &mut usize,
+    stdout: &mut W,
+) -> io::Result<()> {
+    let (width, height) = termion::terminal_size().unwrap_or((80, 24));
+    let (width, height) = (width as usize, (height as usize).saturating_sub(2).max(1));
+    // Scroll just enough to keep the selection on screen.
+    if selected < *top {
+        *top = selected;
+    } else if selected >= *top + height {
+        *top = selected + 1 - height;
+    }
+
+    write!(
+        stdout,
+        "{}{}{} threads. `j`/`k` move, space expands, enter opens a post, `q` exits.",
+        clear::All,
+        cursor::Goto(1, 1),
+        threads.len()
+    )?;
+    let rows = rows(threads, expanded);
+    for (i, row) in rows.iter().enumerate().skip(*top).take(height) {
+        let line = match *row {
+            Row::Thread(t) => {
+                let thread = &threads[t];
+                let marker = if thread.replies.is_empty() {
+                    "   "
+                } else if expanded[t] {
+                    "[-]"
+                } else {
+                    "[+]"
+                };
+                let summary = match &thread.root {
+                    Some(v) => summary(v),
+                    None => format!("(root {} not in this log)", thread.key),
+                };
+                format!("{} {}  ({} replies)", marker, summary, thread.replies.len())
+            }
+            Row::Reply(t, r) => format!("      └ {}", summary(&threads[t].replies[r])),
+        };
+        let line: String = line.chars().take(width).collect();
+        write!(stdout, "\n\r")?;
+        if i == selected {
+            write!(stdout, "{}{}{}", style::Invert, line, style::Reset)?;
+        } else {
+            write!(stdout, "{}", line)?;
+        }
+    }
+    stdout.flush()
+}
+
+// `date  author  first line of text`, for a line in the list.
+fn summary(v: &Value) -> String {
+    let text = msg::content(v)
+        .and_then(|c| c.get("text"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("");
+    format!(
+        "{}  {}  {}",
+        msg::asserted_timestamp(v)
+            .map(render::format_date)
+            .unwrap_or_default(),
+        msg::author(v).map_or("?", |a| a.get(..10).unwrap_or(a)),
+        text.lines()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or("")
+            .trim()
+    )
+}
+
+fn draw_post<W: Write>(v: &Value, markdown: bool, stdout: &mut W) -> io::Result<()> {
+    let width = termion::terminal_size().map_or(80, |(w, _)| w as usize);
+    write!(
+        stdout,
+        "{}{}Press any key to go back to the threads.\n\r",
+        clear::All,
+        cursor::Goto(1, 1)
+    )?;
+    for line in render::render(v, width, markdown).lines() {
+        write!(stdout, "\n\r{}", line)?;
+    }
+    stdout.flush()
+}
@@ -0,0 +1,250 @@
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::render::{civil_from_days, days_from_civil};
+use crate::units;
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+// How messages are grouped over time. Calendar buckets start at midnight in
+// the timeline's time zone; `Every` buckets are aligned to the epoch there.
+pub enum Bucket {
+    // ISO 8601 weeks, Monday to Sunday, labelled `2020-W37`.
+    Week,
+    Month,
+    Quarter,
+    Every(Duration),
+}
+
+// A fixed offset from UTC like `+02:00`, `-0500` or `+9`, in minutes; `UTC`
+// or `Z` is 0. Offsets don't follow daylight saving time.
+pub fn parse_offset(s: &str) -> Option<i64> {
+    if s == "UTC" || s == "Z" {
+        return Some(0);
+    }
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let rest = s[1..].replace(':', "");
+    if rest.is_empty() || rest.len() > 4 || !rest.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, mins) = if rest.len() > 2 {
+        rest.split_at(rest.len() - 2)
+    } else {
+        (rest.as_str(), "0")
+    };
+    let (hours, mins): (i64, i64) = (hours.parse().ok()?, mins.parse().ok()?);
+    if hours > 14 || mins > 59 {
+        return None;
+    }
+    Some(sign * (hours * 60 + mins))
+}
+
+// A UTC time in ms as local time `offset` minutes from UTC, still in ms.
+fn local(utc: f64, offset: i64) -> i64 {
+    utc as i64 + offset * 60 * 1000
+}
+
+impl Bucket {
+    // The start of the bucket `local` falls in, and its label; both in local
+    // time, as ms since the epoch.
+    fn bucket(&self, local: i64) -> (i64, String) {
+        let days = local.div_euclid(DAY_MS);
+        let (year, month, _) = civil_from_days(days);
+        match self {
+            Bucket::Week => {
+                // 1970-01-01 was a Thursday; the week belongs to the year its
+                // Thursday is in.
+                let monday = days - (days + 3).rem_euclid(7);
+                let (year, _, _) = civil_from_days(monday + 3);
+                let week = (monday + 3 - days_from_civil(year, 1, 1)) / 7 + 1;
+                (monday * DAY_MS, format!("{}-W{:02}", year, week))
+            }
+            Bucket::Month => (
+                days_from_civil(year, month, 1) * DAY_MS,
+                format!("{}-{:02}", year, month),
+            ),
+            Bucket::Quarter => {
+                let quarter = (month - 1) / 3;
+                (
+                    days_from_civil(year, quarter * 3 + 1, 1) * DAY_MS,
+                    format!("{}-Q{}", year, quarter + 1),
+                )
+            }
+            Bucket::Every(d) => {
+                let len = (d.as_millis() as i64).max(1);
+                let start = local.div_euclid(len) * len;
+                let secs = start.rem_euclid(DAY_MS) / 1000;
+                let (y, m, d) = civil_from_days(start.div_euclid(DAY_MS));
+                (
+                    start,
+                    format!(
+                        "{:04}-{:02}-{:02} {:02}:{:02}",
+                        y,
+                        m,
+                        d,
+                        secs / 3600,
+                        secs % 3600 / 60
+                    ),
+                )
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Row {
+    label: String,
+    messages: u64,
+    bytes: u64,
+    feeds: HashSet<String>,
+}
+
+// Messages, bytes and active feeds per bucket of asserted (or received)
+// time, `offset` minutes from UTC. Only buckets with messages are listed.
+pub fn timeline_report(
+    log: &OffsetLog<u32>,
+    bucket: &Bucket,
+    offset: i64,
+    received: bool,
+    skip: &HashSet<String>,
+) -> Result<(), Error> {
+    let mut progress = Progress::new(log.end());
+    let mut rows: BTreeMap<i64, Row> = BTreeMap::new();
+    let mut untimed: u64 = 0;
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} buckets", units::count(rows.len() as u64))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => continue,
+        };
+        let author = match msg::author(&v) {
+            Some(a) if !skip.contains(a) => a,
+            _ => continue,
+        };
+        let time = if received {
+            msg::received_timestamp(&v)
+        } else {
+            msg::asserted_timestamp(&v)
+        };
+        let time = match time {
+            Some(t) => t,
+            None => {
+                untimed += 1;
+                continue;
+            }
+        };
+        let (start, label) = bucket.bucket(local(time, offset));
+        let row = rows.entry(start).or_default();
+        if row.label.is_empty() {
+            row.label = label;
+        }
+        row.messages += 1;
+        row.bytes += e.data.len() as u64;
+        if !row.feeds.contains(author) {
+            row.feeds.insert(author.to_string());
+        }
+    }
+    progress.finish(&format!(
+        "Found {} buckets",
+        units::count(rows.len() as u64)
+    ))?;
+
+    println!(
+        "{:<18} {:>12} {:>10} {:>8}",
+        "", "messages", "bytes", "feeds"
+    );
+    for row in rows.values() {
+        println!(
+            "{:<18} {:>12} {:>10} {:>8}",
+            row.label,
+            units::count(row.messages),
+            units::bytes(row.bytes),
+            units::count(row.feeds.len() as u64)
+        );
+    }
+    if untimed > 0 {
+        println!(
+            "{} messages without a {} timestamp were left out.",
+            units::count(untimed),
+            if received { "received" } else { "asserted" }
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_MS: i64 = 60 * 60 * 1000;
+
+    fn at(year: i64, month: i64, day: i64, hour: i64) -> f64 {
+        (days_from_civil(year, month, day) * DAY_MS + hour * HOUR_MS) as f64
+    }
+
+    fn label(bucket: Bucket, utc: f64, tz: &str) -> String {
+        bucket.bucket(local(utc, parse_offset(tz).unwrap())).1
+    }
+
+    #[test]
+    fn iso_weeks_roll_over_at_the_thursday() {
+        // 2020 has 53 ISO weeks; its last runs into 2021.
+        assert_eq!(label(Bucket::Week, at(2020, 12, 28, 0), "UTC"), "2020-W53");
+        assert_eq!(label(Bucket::Week, at(2021, 1, 3, 23), "UTC"), "2020-W53");
+        assert_eq!(label(Bucket::Week, at(2021, 1, 4, 0), "UTC"), "2021-W01");
+        // Late December can be week 1 of the next year.
+        assert_eq!(label(Bucket::Week, at(2019, 12, 29, 23), "UTC"), "2019-W52");
+        assert_eq!(label(Bucket::Week, at(2019, 12, 30, 0), "UTC"), "2020-W01");
+        assert_eq!(label(Bucket::Week, at(2026, 1, 1, 12), "UTC"), "2026-W01");
+
+        let (start, _) = Bucket::Week.bucket(local(at(2021, 1, 3, 12), 0));
+        assert_eq!(start, days_from_civil(2020, 12, 28) * DAY_MS);
+    }
+
+    #[test]
+    fn negative_offset_crosses_midnight() {
+        // 03:00 UTC on New Year's Day is still 22:00 on the 31st at -05:00.
+        let utc = at(2021, 1, 1, 3);
+        assert_eq!(label(Bucket::Month, utc, "UTC"), "2021-01");
+        assert_eq!(label(Bucket::Month, utc, "-05:00"), "2020-12");
+        assert_eq!(label(Bucket::Quarter, utc, "-05:00"), "2020-Q4");
+        assert_eq!(label(Bucket::Week, utc, "-05:00"), "2020-W53");
+        assert_eq!(
+            label(Bucket::Every(Duration::from_secs(6 * 3600)), utc, "-05:00"),
+            "2020-12-31 18:00"
+        );
+
+        let (start, _) = Bucket::Month.bucket(local(utc, -300));
+        assert_eq!(start, days_from_civil(2020, 12, 1) * DAY_MS);
+
+        // And a positive one the other way.
+        assert_eq!(
+            label(Bucket::Month, at(2020, 12, 31, 23), "+02:00"),
+            "2021-01"
+        );
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse_offset("UTC"), Some(0));
+        assert_eq!(parse_offset("Z"), Some(0));
+        assert_eq!(parse_offset("-05:00"), Some(-300));
+        assert_eq!(parse_offset("+0530"), Some(330));
+        assert_eq!(parse_offset("+9"), Some(540));
+        assert_eq!(parse_offset("05:00"), None);
+        assert_eq!(parse_offset("+15:00"), None);
+        assert_eq!(parse_offset("+02:60"), None);
+        assert_eq!(parse_offset("-"), None);
+    }
+}
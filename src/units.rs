@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set from the global `--raw-numbers` flag, for scripts that parse the output.
+static RAW: AtomicBool = AtomicBool::new(false);
+
+pub fn set_raw(raw: bool) {
+    RAW.store(raw, Ordering::Relaxed);
+}
+
+// `n` with thousands separators: 2,345,678.
+pub fn count(n: u64) -> String {
+    let digits = n.to_string();
+    if RAW.load(Ordering::Relaxed) {
+        return digits;
+    }
+    let mut s = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            s.push(',');
+        }
+        s.push(c);
+    }
+    s
+}
+
+// A size in bytes, in binary units past 1 KiB: 812 bytes, 1.4 GiB.
+pub fn bytes(n: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if RAW.load(Ordering::Relaxed) {
+        return format!("{} bytes", n);
+    }
+    if n < 1024 {
+        return format!("{} bytes", n);
+    }
+    let mut size = n as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if size < 10.0 {
+        format!("{:.1} {}", size, UNITS[unit])
+    } else {
+        format!("{:.0} {}", size, UNITS[unit])
+    }
+}
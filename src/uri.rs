@@ -0,0 +1,80 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// SSB URIs, which newer clients use in place of sigil ids:
+//
+//   @<base64>.ed25519  ssb:feed/classic/<base64url>
+//   %<base64>.sha256   ssb:message/classic/<base64url>
+//   &<base64>.sha256   ssb:blob/classic/<base64url>
+//
+// The older `ssb:feed/ed25519/...` and `ssb:message/sha256/...` forms are
+// read too, but only `classic` ones are written.
+const KINDS: &[(&str, &[&str], char, &str)] = &[
+    ("feed", &["classic", "ed25519"], '@', ".ed25519"),
+    ("message", &["classic", "sha256"], '%', ".sha256"),
+    ("blob", &["classic", "sha256"], '&', ".sha256"),
+];
+
+// Set from the global `--uri` flag.
+static URI: AtomicBool = AtomicBool::new(false);
+
+pub fn set_uri(uri: bool) {
+    URI.store(uri, Ordering::Relaxed);
+}
+
+// The sigil id an SSB URI stands for, or None if `s` isn't the URI of a
+// feed, message or blob.
+pub fn parse(s: &str) -> Option<String> {
+    let mut parts = s.strip_prefix("ssb:")?.splitn(3, '/');
+    let (kind, format, data) = (parts.next()?, parts.next()?, parts.next()?);
+    let (_, _, sigil, suffix) = KINDS
+        .iter()
+        .find(|(k, formats, _, _)| *k == kind && formats.contains(&format))?;
+    let mut b64: String = data
+        .chars()
+        .map(|c| match c {
+            '-' => '+',
+            '_' => '/',
+            c => c,
+        })
+        .collect();
+    while !b64.len().is_multiple_of(4) {
+        b64.push('=');
+    }
+    match base64::decode(&b64) {
+        Ok(bytes) if bytes.len() == 32 => Some(format!("{}{}{}", sigil, b64, suffix)),
+        _ => None,
+    }
+}
+
+// A feed id or message key as given on the command line or in a file, which
+// may be an SSB URI. Anything else is taken as it is. None for a URI that
+// doesn't parse.
+pub fn id(s: &str) -> Option<Cow<'_, str>> {
+    if s.starts_with("ssb:") {
+        parse(s).map(Cow::Owned)
+    } else {
+        Some(Cow::Borrowed(s))
+    }
+}
+
+// An id as reports print it: its SSB URI with `--uri`, otherwise the id
+// itself, as are ids that have no URI.
+pub fn show(id: &str) -> Cow<'_, str> {
+    if !URI.load(Ordering::Relaxed) {
+        return Cow::Borrowed(id);
+    }
+    let uri = KINDS.iter().find_map(|(kind, formats, sigil, suffix)| {
+        let b64 = id.strip_prefix(*sigil)?.strip_suffix(*suffix)?;
+        let data: String = b64
+            .chars()
+            .map(|c| match c {
+                '+' => '-',
+                '/' => '_',
+                c => c,
+            })
+            .collect();
+        Some(format!("ssb:{}/{}/{}", kind, formats[0], data))
+    });
+    uri.map(Cow::Owned).unwrap_or(Cow::Borrowed(id))
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::render;
+use crate::units;
+
+pub struct Link {
+    pub count: u64,
+    // Earliest asserted timestamp, and the key of that message.
+    pub first_seen: f64,
+    pub first_key: String,
+}
+
+// Every external (http or https) URL in the text of posts, by URL.
+pub fn scan(log: &OffsetLog<u32>) -> Result<HashMap<String, Link>, Error> {
+    let mut progress = Progress::new(log.end());
+    let mut links: HashMap<String, Link> = HashMap::new();
+
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Found {} URLs", units::count(links.len() as u64))
+        })?;
+        let v = match msg::parse(&e) {
+            Some(v) if msg::content_type(&v) == Some("post") => v,
+            _ => continue,
+        };
+        let text = match msg::content(&v)
+            .and_then(|c| c.get("text"))
+            .and_then(|t| t.as_str())
+        {
+            Some(t) => t,
+            None => continue,
+        };
+        let time = msg::asserted_timestamp(&v).unwrap_or(0.0);
+        let key = msg::key(&v).unwrap_or("");
+        for url in find_urls(text) {
+            let link = links.entry(url.to_string()).or_insert_with(|| Link {
+                count: 0,
+                first_seen: time,
+                first_key: key.to_string(),
+            });
+            link.count += 1;
+            if time < link.first_seen {
+                link.first_seen = time;
+                link.first_key = key.to_string();
+            }
+        }
+    }
+    progress.finish(&format!("Found {} URLs", units::count(links.len() as u64)))?;
+    Ok(links)
+}
+
+// URLs in `text`, ending at whitespace or a closing bracket or quote (as in
+// markdown links), without trailing punctuation.
+fn find_urls(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let start = ["http://", "https://"]
+            .iter()
+            .filter_map(|scheme| rest.find(scheme))
+            .min()?;
+        let url = &rest[start..];
+        let end = url
+            .find(|c: char| c.is_whitespace() || "<>()[]\"'`".contains(c))
+            .unwrap_or(url.len());
+        rest = &url[end..];
+        Some(url[..end].trim_end_matches(|c: char| ".,;:!?*_".contains(c)))
+    })
+    .filter(|u| !u.ends_with("://"))
+}
+
+// Most linked first, then by URL.
+fn sorted(links: &HashMap<String, Link>) -> Vec<(&String, &Link)> {
+    let mut links: Vec<_> = links.iter().collect();
+    links.sort_by_key(|(url, l)| (std::cmp::Reverse(l.count), *url));
+    links
+}
+
+pub fn report(links: &HashMap<String, Link>, top: usize) {
+    println!(
+        "{} distinct URLs, linked {} times.",
+        links.len(),
+        links.values().map(|l| l.count).sum::<u64>()
+    );
+    for (url, link) in sorted(links).into_iter().take(top) {
+        println!(
+            "{:>8}  {}  {}",
+            link.count,
+            render::format_date(link.first_seen),
+            url
+        );
+    }
+}
+
+// `url,count,first_seen,first_key` rows, first_seen as `YYYY-MM-DDTHH:MMZ`.
+pub fn write_csv<W: Write>(links: &HashMap<String, Link>, out: W) -> Result<(), Error> {
+    let mut out = BufWriter::new(out);
+    writeln!(out, "url,count,first_seen,first_key")?;
+    for (url, link) in sorted(links) {
+        let date = render::format_date(link.first_seen);
+        // "YYYY-MM-DD HH:MM UTC" -> "YYYY-MM-DDTHH:MMZ"
+        let date = date.replacen(' ', "T", 1).replace(" UTC", "Z");
+        writeln!(
+            out,
+            "{},{},{},{}",
+            csv_field(url),
+            link.count,
+            date,
+            link.first_key
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// URLs can contain commas and quotes, so quote them when they do.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+
+use flumedb::flume_log::Error;
+use flumedb::offset_log::OffsetLog;
+use serde_json::Value;
+
+use crate::msg;
+use crate::progress::Progress;
+use crate::signature;
+use crate::units;
+
+// The first entry of a log that doesn't validate, and why.
+pub struct Failure {
+    pub offset: u64,
+    // Entries before it, which all validate.
+    pub valid: u64,
+    pub author: Option<String>,
+    pub sequence: Option<u64>,
+    pub why: String,
+}
+
+// Check every message in order: its signature and key, and that it follows
+// the previous message of its feed in the log (the next sequence number,
+// with `previous` pointing at it). A feed may start after #1, since
+// extracted logs often have partial history.
+pub fn first_failure(log: &OffsetLog<u32>) -> Result<Option<Failure>, Error> {
+    let mut progress = Progress::new(log.end());
+    // feed -> (sequence, key) of its latest message so far
+    let mut latest: HashMap<String, (u64, String)> = HashMap::new();
+    let mut valid: u64 = 0;
+
+    for e in log.iter() {
+        progress.update(e.offset, || {
+            format!("Validated {} messages", units::count(valid))
+        })?;
+        let fail = |v: Option<&Value>, why: String| {
+            Some(Failure {
+                offset: e.offset,
+                valid,
+                author: v.and_then(msg::author).map(str::to_string),
+                sequence: v.and_then(msg::sequence),
+                why,
+            })
+        };
+        let v = match msg::parse(&e) {
+            Some(v) => v,
+            None => return Ok(fail(None, "not a JSON message".to_string())),
+        };
+        if let Err(why) = signature::verify(&v) {
+            return Ok(fail(Some(&v), why.to_string()));
+        }
+        let (author, seq, key) = match (msg::author(&v), msg::sequence(&v), msg::key(&v)) {
+            (Some(a), Some(s), Some(k)) => (a, s, k),
+            _ => return Ok(fail(Some(&v), "no author, sequence or key".to_string())),
+        };
+        let previous = v
+            .get("value")
+            .and_then(|v| v.get("previous"))
+            .and_then(Value::as_str);
+        if let Some((last_seq, last_key)) = latest.get(author) {
+            if seq != last_seq + 1 {
+                let why = format!("follows #{} of its feed in the log", last_seq);
+                return Ok(fail(Some(&v), why));
+            }
+            if previous != Some(last_key.as_str()) {
+                let why = format!("previous isn't #{} ({})", last_seq, last_key);
+                return Ok(fail(Some(&v), why));
+            }
+        }
+        latest.insert(author.to_string(), (seq, key.to_string()));
+        valid += 1;
+    }
+    progress.finish(&format!("Validated {} messages", units::count(valid)))?;
+    Ok(None)
+}
+
+// Cut the log at `offset`, dropping that entry and everything after it.
+pub fn trim(path: &str, offset: u64) -> Result<(), Error> {
+    OpenOptions::new().write(true).open(path)?.set_len(offset)?;
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flumedb::flume_log::{Error, FlumeLog};
+use flumedb::offset_log::OffsetLog;
+use fs2::FileExt;
+use serde_json::Value;
+
+use crate::frame;
+use crate::msg;
+use crate::progress::Progress;
+use crate::units;
+
+// A vault is a directory that logs can be added to again and again, keeping
+// every message (by key) once:
+//   messages.offset  each unique message, in the order it was first added
+//   keys             one `<offset> <key>` line per message in messages.offset,
+//                    and a `- <key>` line per message removed by `gc`, so it
+//                    isn't added back
+//   lock             held while a command uses the vault
+pub struct Vault {
+    dir: PathBuf,
+    log: OffsetLog<u32>,
+    keys: HashMap<String, u64>,
+    dropped: HashSet<String>,
+    index: File,
+    _lock: File,
+}
+
+// What `gc` keeps. Messages by `keep_feeds` are always kept; otherwise
+// messages older than `max_age`, and votes older than `vote_max_age`, are
+// dropped. Ages are in ms, by the message's asserted time.
+pub struct Retention {
+    pub keep_feeds: HashSet<String>,
+    pub max_age: Option<f64>,
+    pub vote_max_age: Option<f64>,
+}
+
+// What `materialize` writes: messages by `feeds` (every feed if None), of
+// `types` (every type if empty), and no older than `max_age` ms unless their
+// type is one of `priority_types`.
+pub struct Selection {
+    pub feeds: Option<HashSet<String>>,
+    pub types: Vec<String>,
+    pub max_age: Option<f64>,
+    pub priority_types: Vec<String>,
+}
+
+impl Selection {
+    fn selects(&self, v: &Value, now: f64) -> bool {
+        if let Some(feeds) = &self.feeds {
+            if !msg::author(v).is_some_and(|a| feeds.contains(a)) {
+                return false;
+            }
+        }
+        let typ = msg::content_type(v);
+        let has_type = |types: &[String]| typ.is_some_and(|t| types.iter().any(|x| x == t));
+        if !self.types.is_empty() && !has_type(&self.types) {
+            return false;
+        }
+        match (self.max_age, msg::asserted_timestamp(v)) {
+            (Some(max), Some(t)) if now - t > max => has_type(&self.priority_types),
+            _ => true,
+        }
+    }
+}
+
+impl Retention {
+    fn keeps(&self, v: &Value, now: f64) -> bool {
+        if msg::author(v).is_some_and(|a| self.keep_feeds.contains(a)) {
+            return true;
+        }
+        let age = match msg::asserted_timestamp(v) {
+            Some(t) => now - t,
+            None => return true,
+        };
+        if self.max_age.is_some_and(|max| age > max) {
+            return false;
+        }
+        !(msg::content_type(v) == Some("vote") && self.vote_max_age.is_some_and(|max| age > max))
+    }
+}
+
+impl Vault {
+    // Open the vault at `dir`, creating it if needed. Holds a lock on the
+    // vault until dropped, so two commands can't add to it at once.
+    pub fn open(dir: &Path) -> Result<Vault, Error> {
+        fs::create_dir_all(dir)?;
+        let lock = File::create(dir.join("lock"))?;
+        if lock.try_lock_exclusive().is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("vault `{}` is in use by another command", dir.display()),
+            )
+            .into());
+        }
+        finish_gc(dir)?;
+        let index = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join("keys"))?;
+
+        let log_path = dir.join("messages.offset");
+        let log = OffsetLog::<u32>::new(&log_path)?;
+        let mut vault = Vault {
+            dir: dir.to_path_buf(),
+            log,
+            keys: HashMap::new(),
+            dropped: HashSet::new(),
+            index,
+            _lock: lock,
+        };
+        vault.load_keys()?;
+        Ok(vault)
+    }
+
+    fn load_keys(&mut self) -> Result<(), Error> {
+        let mut last = None;
+        for line in BufReader::new(&self.index).lines() {
+            let line = line?;
+            let mut parts = line.splitn(2, ' ');
+            let offset = parts.next();
+            match (offset, parts.next()) {
+                (Some("-"), Some(key)) => {
+                    self.dropped.insert(key.to_string());
+                }
+                (Some(offset), Some(key)) if offset.parse::<u64>().is_ok() => {
+                    let offset = offset.parse().unwrap();
+                    self.keys.insert(key.to_string(), offset);
+                    last = Some(offset);
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("bad line in {}: {}", self.dir.join("keys").display(), line),
+                    )
+                    .into())
+                }
+            }
+        }
+
+        // An interrupted `add` may have appended messages without recording
+        // their keys; index anything after the last recorded message.
+        let log_path = self.dir.join("messages.offset");
+        let mut frames = frame::frames_from(&log_path, last.unwrap_or(0))?;
+        if last.is_some() {
+            frames.next().transpose()?;
+        }
+        for f in frames {
+            let f = f?;
+            if let Some(key) = serde_json::from_slice::<Value>(&f.data)
+                .ok()
+                .as_ref()
+                .and_then(msg::key)
+            {
+                self.record(key, f.offset)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, key: &str, offset: u64) -> io::Result<()> {
+        writeln!(self.index, "{} {}", offset, key)?;
+        self.keys.insert(key.to_string(), offset);
+        Ok(())
+    }
+
+    pub fn log(&self) -> &OffsetLog<u32> {
+        &self.log
+    }
+
+    pub fn message_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    // The size of the vault's message log.
+    pub fn size(&self) -> u64 {
+        self.log.end()
+    }
+
+    // Copy the messages of `in_log` that aren't in the vault yet.
+    pub fn add(&mut self, in_log: &OffsetLog<u32>) -> Result<(), Error> {
+        let mut progress = Progress::new(in_log.end());
+        let mut added: u64 = 0;
+        let mut known: u64 = 0;
+        let mut skipped: u64 = 0;
+
+        for e in in_log.iter() {
+            progress.update(e.offset, || {
+                format!(
+                    "Added {} messages, {} already in the vault",
+                    units::count(added),
+                    units::count(known)
+                )
+            })?;
+
+            let v = match msg::parse(&e) {
+                Some(v) => v,
+                None => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            let key = match msg::key(&v) {
+                Some(k) => k,
+                None => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            if self.keys.contains_key(key) || self.dropped.contains(key) {
+                known += 1;
+                continue;
+            }
+            let offset = self.log.append(&e.data)?;
+            self.record(key, offset)?;
+            added += 1;
+        }
+        self.index.sync_data()?;
+        progress.finish(&format!(
+            "Added {} messages, {} already in the vault",
+            units::count(added),
+            units::count(known)
+        ))?;
+
+        if skipped > 0 {
+            eprintln!(
+                "Skipped {} entries that aren't ssb messages",
+                units::count(skipped)
+            );
+        }
+        println!(
+            "Added {} new messages ({} were already in the vault); it now holds {}.",
+            units::count(added),
+            units::count(known),
+            units::count(self.message_count() as u64)
+        );
+        Ok(())
+    }
+
+    // Write the vault's messages chosen by `selection` to `out_log`.
+    pub fn materialize(
+        &self,
+        mut out_log: OffsetLog<u32>,
+        selection: &Selection,
+    ) -> Result<(), Error> {
+        let now = now_ms();
+        let mut progress = Progress::new(self.log.end());
+        let mut count: u64 = 0;
+
+        for e in self.log.iter() {
+            if msg::parse(&e).is_some_and(|v| selection.selects(&v, now)) {
+                out_log.append(&e.data)?;
+                count += 1;
+            }
+            progress.update(e.offset, || {
+                format!("Wrote {} messages", units::count(count))
+            })?;
+        }
+        progress.finish(&format!("Wrote {} messages", units::count(count)))?;
+        println!(
+            "Wrote {} of the vault's {} messages.",
+            units::count(count),
+            units::count(self.message_count() as u64)
+        );
+        Ok(())
+    }
+
+    // Rewrite the vault without the messages `rules` don't keep, remembering
+    // their keys so they aren't added again. With `dry_run`, only report what
+    // would be dropped.
+    pub fn gc(self, rules: &Retention, dry_run: bool) -> Result<(), Error> {
+        let now = now_ms();
+        let log_path = self.dir.join("messages.offset");
+        let new_log_path = self.dir.join("messages.offset.gc");
+        let new_keys_path = self.dir.join("keys.gc");
+
+        let mut new_log = if dry_run {
+            None
+        } else {
+            Some(OffsetLog::<u32>::from_file(File::create(&new_log_path)?)?)
+        };
+        let mut new_keys = Vec::new();
+        let mut kept: u64 = 0;
+        let mut dropped: Vec<String> = Vec::new();
+        let mut dropped_bytes: u64 = 0;
+        let mut progress = Progress::new(self.log.end());
+
+        for e in self.log.iter() {
+            progress.update(e.offset, || {
+                format!(
+                    "Kept {} messages, dropped {}",
+                    units::count(kept),
+                    units::count(dropped.len() as u64)
+                )
+            })?;
+            let v = match msg::parse(&e) {
+                Some(v) => v,
+                None => continue,
+            };
+            let key = match msg::key(&v) {
+                Some(k) => k,
+                None => continue,
+            };
+            if rules.keeps(&v, now) {
+                if let Some(log) = &mut new_log {
+                    let offset = log.append(&e.data)?;
+                    writeln!(new_keys, "{} {}", offset, key)?;
+                }
+                kept += 1;
+            } else {
+                dropped.push(key.to_string());
+                dropped_bytes += e.data.len() as u64 + frame::FRAMING_SIZE;
+            }
+        }
+        progress.finish(&format!(
+            "Kept {} messages, dropped {}",
+            units::count(kept),
+            units::count(dropped.len() as u64)
+        ))?;
+
+        if dry_run {
+            println!(
+                "Would drop {} of {} messages, reclaiming {}.",
+                units::count(dropped.len() as u64),
+                units::count(self.message_count() as u64),
+                units::bytes(dropped_bytes)
+            );
+            return Ok(());
+        }
+
+        for key in self.dropped.iter().chain(&dropped) {
+            writeln!(new_keys, "- {}", key)?;
+        }
+        let new_log = new_log.unwrap();
+        let new_size = new_log.end();
+        drop(new_log);
+        File::open(&new_log_path)?.sync_all()?;
+        let mut keys_file = File::create(&new_keys_path)?;
+        keys_file.write_all(&new_keys)?;
+        keys_file.sync_all()?;
+
+        // `open` finishes the job if we're interrupted between the renames.
+        let old_size = self.log.end();
+        fs::rename(&new_log_path, &log_path)?;
+        fs::rename(&new_keys_path, self.dir.join("keys"))?;
+
+        println!(
+            "Dropped {} of {} messages; reclaimed {}.",
+            units::count(dropped.len() as u64),
+            units::count(self.message_count() as u64),
+            units::bytes(old_size - new_size)
+        );
+        Ok(())
+    }
+}
+
+// An interrupted `gc` leaves its new files behind. Once both are complete
+// (the keys are written last) and the log has been moved into place, only the
+// keys are left to move; anything earlier is rolled back.
+fn finish_gc(dir: &Path) -> io::Result<()> {
+    let new_log = dir.join("messages.offset.gc");
+    let new_keys = dir.join("keys.gc");
+    if new_keys.exists() && !new_log.exists() {
+        return fs::rename(new_keys, dir.join("keys"));
+    }
+    for p in &[new_log, new_keys] {
+        if p.exists() {
+            fs::remove_file(p)?;
+        }
+    }
+    Ok(())
+}
+
+fn now_ms() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as f64)
+        .unwrap_or(0.0)
+}
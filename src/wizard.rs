@@ -0,0 +1,162 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flumedb::flume_log::Error;
+use serde_json::Value;
+
+// Walk through the common tasks with prompts, running the regular
+// subcommands (as a child process) with safe defaults. Nothing is ever
+// overwritten.
+pub fn wizard() -> Result<(), Error> {
+    println!("feedrick wizard. Press enter to accept [defaults].");
+    println!();
+
+    let default_dir = dirs::home_dir()
+        .map(|h| h.join(".ssb"))
+        .unwrap_or_else(|| PathBuf::from(".ssb"));
+    let ssb_dir = PathBuf::from(ask("ssb directory", &default_dir.to_string_lossy())?);
+    let log_path = ssb_dir.join("flume").join("log.offset");
+    if !log_path.exists() {
+        println!("No log found at {}", log_path.display());
+        return Ok(());
+    }
+
+    loop {
+        println!();
+        println!("What would you like to do?");
+        println!("  1) Back up my feed");
+        println!("  2) Clean my log");
+        println!("  3) Check my log for problems");
+        println!("  q) Quit");
+        match ask("choice", "q")?.as_str() {
+            "1" => backup_my_feed(&ssb_dir, &log_path)?,
+            "2" => clean_my_log(&log_path)?,
+            "3" => {
+                run(&["doctor", &ssb_dir.to_string_lossy()])?;
+            }
+            "q" | "Q" => return Ok(()),
+            other => println!("Unknown choice `{}`", other),
+        }
+    }
+}
+
+fn backup_my_feed(ssb_dir: &Path, log_path: &Path) -> Result<(), Error> {
+    let id = match own_id(ssb_dir) {
+        Some(id) => id,
+        None => {
+            println!(
+                "Couldn't read your feed id from {}",
+                ssb_dir.join("secret").display()
+            );
+            ask("your feed id (@...)", "")?
+        }
+    };
+    if !id.starts_with('@') {
+        println!("`{}` doesn't look like a feed id.", id);
+        return Ok(());
+    }
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let out = ask(
+        "save backup to",
+        &format!("feedrick-backup-{}.offset", secs),
+    )?;
+    if Path::new(&out).exists() {
+        println!("`{}` already exists; pick another name.", out);
+        return Ok(());
+    }
+
+    let log = log_path.to_string_lossy();
+    let args = ["extract", "--in", &log, "--out", &out, "--feed", &id];
+    println!();
+    println!("This will run: feedrick {}", args.join(" "));
+    if confirm("Proceed?")? && run(&args)? {
+        println!("Your feed was copied to {}", out);
+    }
+    Ok(())
+}
+
+// Copy the log without deleted entries (compact) or repeated messages
+// (dedupe) into a new file; the log itself is left as it is.
+fn clean_my_log(log_path: &Path) -> Result<(), Error> {
+    println!("Leave out:");
+    println!("  1) deleted (zero-filled) entries");
+    println!("  2) messages that appear more than once");
+    let command = match ask("choice", "1")?.as_str() {
+        "1" => "compact",
+        "2" => "dedupe",
+        other => {
+            println!("Unknown choice `{}`", other);
+            return Ok(());
+        }
+    };
+
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let out = ask(
+        "save the cleaned log to",
+        &format!("feedrick-{}-{}.offset", command, secs),
+    )?;
+    if Path::new(&out).exists() {
+        println!("`{}` already exists; pick another name.", out);
+        return Ok(());
+    }
+
+    let log = log_path.to_string_lossy();
+    let args = [command, "--in", &log, "--out", &out];
+    println!();
+    println!("This will run: feedrick {}", args.join(" "));
+    println!("Your log is only read; the cleaned copy goes to the new file.");
+    if confirm("Proceed?")? && run(&args)? {
+        println!("The cleaned log is in {}", out);
+    }
+    Ok(())
+}
+
+// The feed id from `<ssb dir>/secret`, which is JSON preceded by `#` comment lines.
+pub fn own_id(ssb_dir: &Path) -> Option<String> {
+    let secret = fs::read_to_string(ssb_dir.join("secret")).ok()?;
+    let json: String = secret
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .collect();
+    let v: Value = serde_json::from_str(&json).ok()?;
+    v.get("id").and_then(|id| id.as_str()).map(String::from)
+}
+
+// Run a feedrick subcommand; returns whether it succeeded.
+fn run(args: &[&str]) -> Result<bool, Error> {
+    let status = Command::new(env::current_exe()?).args(args).status()?;
+    if !status.success() {
+        println!("feedrick {} failed ({})", args[0], status);
+    }
+    Ok(status.success())
+}
+
+fn ask(question: &str, default: &str) -> io::Result<String> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+fn confirm(question: &str) -> io::Result<bool> {
+    let answer = ask(&format!("{} (y/n)", question), "n")?;
+    Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}